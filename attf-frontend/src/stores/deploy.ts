@@ -54,7 +54,14 @@ export const useDeployStore = defineStore('deploy', {
     },
     deploy() {
       let router = useRouter()
-      fetch('/game/' + this.gameId + '/deploy', { method: 'POST', headers: { 'Content-type': 'application/json' }, body: JSON.stringify({ scenario_player_id: this.idx, entities: this.entityList }) }).then(() => router.push("/play/" + this.gameId))
+      fetch('/game/' + this.gameId + '/deploy', { method: 'POST', headers: { 'Content-type': 'application/json' }, body: JSON.stringify({ scenario_player_id: this.idx, entities: this.entityList }) }).then((r) => {
+        if (r.ok) {
+          fetch('/game/' + this.gameId + '/seat/' + this.idx + '/ready', { method: 'PUT', headers: { 'Content-type': 'application/json' }, body: 'true' })
+            .then(() => router.push("/play/" + this.gameId))
+        } else {
+          r.json().then((rejection) => alert("Deployment rejected: " + rejection.reason))
+        }
+      })
     }
   }
 }
@@ -5,6 +5,19 @@ import { reactive } from 'vue'
 function onmessage(event: any, ws: WebSocket) {
   const messageObj = JSON.parse(event.data)
   const grid = useGridStore()
+  if (messageObj.draft !== undefined) {
+    grid.draft = messageObj.draft
+    return
+  }
+  if (messageObj.handoff !== undefined) {
+    grid.entities = {}
+    grid.entities_by_id = {}
+    grid.grid = {}
+    if (window.confirm('Pass the device to seat ' + messageObj.handoff.seat + ', then confirm')) {
+      fetch('/game/' + grid.gameId + '/handoff', { method: 'POST' })
+    }
+    return
+  }
 
   grid.entities = {}
   grid.entities_by_id = {}
@@ -148,7 +161,7 @@ export const useGridStore = defineStore('grid', {
 
     const isPlaying = false
 
-    return { grid, entities, playing, isPlaying, user, actionLog, curX, curY, entities_by_id, abilities, ability, gameId }
+    return { grid, entities, playing, isPlaying, user, actionLog, curX, curY, entities_by_id, abilities, ability, gameId, draft: null }
   },
   getters: {
     flat_grid(state) {
@@ -10,13 +10,23 @@ export default defineConfig({
   ],
   server: {
     proxy: {
-      '^/game/[0-9a-f-]*/ws': {
+      '^/game/[0-9a-f-]*/(spectate/)?ws': {
         target: "ws://localhost:8061",
-        ws: true
+        ws: true,
+        xfwd: true
       },
-      '/game': "http://localhost:8061",
-      '/scenario': "http://localhost:8061",
-      '/login': "http://localhost:8061",
+      '/game': { target: "http://localhost:8061", xfwd: true },
+      '/scenario': { target: "http://localhost:8061", xfwd: true },
+      '/login': { target: "http://localhost:8061", xfwd: true },
+      '/create_user': { target: "http://localhost:8061", xfwd: true },
+      '/logout': { target: "http://localhost:8061", xfwd: true },
+      '/guest': { target: "http://localhost:8061", xfwd: true },
+      '/account': { target: "http://localhost:8061", xfwd: true },
+      '/user': { target: "http://localhost:8061", xfwd: true },
+      '/invite': { target: "http://localhost:8061", xfwd: true },
+      '/challenges': { target: "http://localhost:8061", xfwd: true },
+      '/matchmaking': { target: "http://localhost:8061", xfwd: true },
+      '/admin': { target: "http://localhost:8061", xfwd: true },
     }
   },
   resolve: {
@@ -0,0 +1,148 @@
+//! Rules for usernames, passwords and display names.
+
+use std::fmt;
+
+use password_hash::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+const USERNAME_LENGTH: std::ops::RangeInclusive<usize> = 3..=32;
+const PASSWORD_LENGTH: std::ops::RangeInclusive<usize> = 8..=128;
+const DISPLAY_NAME_LENGTH: std::ops::RangeInclusive<usize> = 1..=32;
+/// Reserved for the generated ids of guests.
+pub const GUEST_PREFIX: &str = "guest-";
+/// Guests without a session or a game are deleted after this long.
+pub const GUEST_TTL_MS: i64 = 7 * 24 * 60 * 60_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AccountError {
+    UsernameTaken,
+    InvalidUsername,
+    ReservedUsername,
+    PasswordLength,
+    WeakPassword,
+    PasswordIsUsername,
+    InvalidDisplayName,
+    StillPlaying,
+    NotAGuest,
+    GuestNotAllowed,
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UsernameTaken => write!(f, "Username is already taken"),
+            Self::InvalidUsername => write!(
+                f,
+                "Usernames are {} to {} lowercase letters, digits or dashes",
+                USERNAME_LENGTH.start(),
+                USERNAME_LENGTH.end()
+            ),
+            Self::ReservedUsername => write!(f, "Usernames can't start with {}", GUEST_PREFIX),
+            Self::PasswordLength => write!(
+                f,
+                "Passwords are {} to {} characters long",
+                PASSWORD_LENGTH.start(),
+                PASSWORD_LENGTH.end()
+            ),
+            Self::WeakPassword => write!(f, "Passwords need both letters and digits"),
+            Self::PasswordIsUsername => write!(f, "Password can't be the username"),
+            Self::InvalidDisplayName => write!(
+                f,
+                "Display name must be {} to {} characters long",
+                DISPLAY_NAME_LENGTH.start(),
+                DISPLAY_NAME_LENGTH.end()
+            ),
+            Self::StillPlaying => write!(f, "Leave or finish your games first"),
+            Self::NotAGuest => write!(f, "Only guest accounts can be upgraded"),
+            Self::GuestNotAllowed => write!(f, "Create an account to do this"),
+        }
+    }
+}
+
+/// Lowercases the username, which must then already be a slug, so it is safe to
+/// use in file names.
+pub fn normalize_username(username: &str) -> Result<String, AccountError> {
+    let username = username.trim().to_lowercase();
+    if !USERNAME_LENGTH.contains(&username.len()) || slug::slugify(&username) != username {
+        return Err(AccountError::InvalidUsername);
+    }
+    if username.starts_with(GUEST_PREFIX) {
+        return Err(AccountError::ReservedUsername);
+    }
+    Ok(username)
+}
+
+/// A fresh id for a guest, along with a display name.
+pub fn guest_identity() -> (String, String) {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+    let suffix: String = (0..10)
+        .map(|_| ALPHABET[OsRng.next_u32() as usize % ALPHABET.len()] as char)
+        .collect();
+    let display_name = format!("Guest {}", &suffix[..4]);
+    (format!("{}{}", GUEST_PREFIX, suffix), display_name)
+}
+
+pub fn check_password(username: &str, password: &str) -> Result<(), AccountError> {
+    if !PASSWORD_LENGTH.contains(&password.chars().count()) {
+        return Err(AccountError::PasswordLength);
+    }
+    if !password.chars().any(char::is_alphabetic) || !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err(AccountError::WeakPassword);
+    }
+    if password.to_lowercase().contains(username) {
+        return Err(AccountError::PasswordIsUsername);
+    }
+    Ok(())
+}
+
+/// Trimmed display name.
+pub fn normalize_display_name(display_name: &str) -> Result<String, AccountError> {
+    let display_name = display_name.trim();
+    if !DISPLAY_NAME_LENGTH.contains(&display_name.chars().count()) {
+        return Err(AccountError::InvalidDisplayName);
+    }
+    Ok(display_name.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize_username() {
+        assert_eq!(normalize_username(" Alice-2 "), Ok("alice-2".to_string()));
+        for username in ["../user_bob", "al", "a b c", "élodie", "-bob", "x/y"] {
+            assert_eq!(
+                normalize_username(username),
+                Err(AccountError::InvalidUsername)
+            );
+        }
+        assert_eq!(
+            normalize_username("Guest-1234"),
+            Err(AccountError::ReservedUsername)
+        );
+        let (guest, _) = guest_identity();
+        assert_eq!(
+            slug::slugify(&guest),
+            guest,
+            "guest ids must be safe file names"
+        );
+    }
+
+    #[test]
+    fn test_check_password() {
+        assert_eq!(check_password("alice", "correct horse 9"), Ok(()));
+        assert_eq!(
+            check_password("alice", "abc123"),
+            Err(AccountError::PasswordLength)
+        );
+        assert_eq!(
+            check_password("alice", "correcthorse"),
+            Err(AccountError::WeakPassword)
+        );
+        assert_eq!(
+            check_password("alice", "Alice12345"),
+            Err(AccountError::PasswordIsUsername)
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Moderation: what admins can do to games and accounts, and the audit log keeping
+//! track of it.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::{Entity, GameRef, Gamestate, UserData};
+
+pub const ADMIN_ROLE: &str = "admin";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AdminAction {
+    FinishGame {
+        game_id: uuid::Uuid,
+        winners: Vec<i64>,
+    },
+    DeleteGame {
+        game_id: uuid::Uuid,
+    },
+    ResetPassword {
+        user_id: String,
+    },
+    Ban {
+        user_id: String,
+    },
+    Unban {
+        user_id: String,
+    },
+    GrantRole {
+        user_id: String,
+        role: String,
+    },
+    RevokeRole {
+        user_id: String,
+        role: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub at: i64,
+    pub admin: String,
+    pub action: AdminAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UserSummary {
+    pub id: String,
+    pub display_name: String,
+    pub roles: Vec<String>,
+    pub banned: bool,
+    pub created_at: i64,
+}
+
+impl From<&UserData> for UserSummary {
+    fn from(user: &UserData) -> Self {
+        Self {
+            id: user.id.clone(),
+            display_name: user.display_name.clone(),
+            roles: user.roles.clone(),
+            banned: user.banned,
+            created_at: user.created_at,
+        }
+    }
+}
+
+/// Everything about a game, hidden deployments included.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GameInspection {
+    pub game: GameRef,
+    /// `None` until the game has started.
+    pub gamestate: Option<Gamestate>,
+    pub deployments: HashMap<i64, Vec<Entity>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FinishRequest {
+    /// Winning seats, none for a draw.
+    #[serde(default)]
+    pub winners: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordReset {
+    pub password: String,
+}
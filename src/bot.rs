@@ -5,16 +5,28 @@ use websocket;
 
 #[tokio::main]
 async fn main() {
-    let params = [("username", "diane_bot"), ("password", "diane_bot")];
+    // An API token in ATTF_TOKEN spares the form login.
+    let token = std::env::var("ATTF_TOKEN").ok();
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(token) = &token {
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+    }
     let client = reqwest::Client::builder()
         .cookie_store(true)
+        .default_headers(headers)
         .build()
         .unwrap();
-    let _res = client
-        .post("http://localhost:5173/login")
-        .form(&params)
-        .send()
-        .await;
+    if token.is_none() {
+        let params = [("username", "diane_bot"), ("password", "diane_bot")];
+        let _res = client
+            .post("http://localhost:5173/login")
+            .form(&params)
+            .send()
+            .await;
+    }
     for game in serde_json::from_slice::<Vec<i64>>(
         client
             .get("http://localhost:5173/game")
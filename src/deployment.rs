@@ -0,0 +1,204 @@
+//! Checks a seat's deployment against what its scenario allows.
+
+use std::{collections::HashMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    charclasses::CharClass,
+    schemas::{Coords, ScenarioPlayer},
+};
+
+/// How long seats have to place their entities once they are all taken.
+pub const DEPLOYMENT_TIME_MS: i64 = 120_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "reason")]
+pub enum DeployRejection {
+    GameNotOpen,
+    UnknownSeat,
+    SeatTaken,
+    SeatsNotTaken,
+    DraftNotOver,
+    NoEntities,
+    NotADropTile { coords: Coords },
+    ClassNotAllowed { class: CharClass },
+    OverBudget { cost: i64, budget: i64 },
+    NotDrafted { class: CharClass },
+}
+
+impl fmt::Display for DeployRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GameNotOpen => write!(f, "Game is not open for deployment"),
+            Self::UnknownSeat => write!(f, "No such seat in this scenario"),
+            Self::SeatTaken => write!(f, "Seat belongs to another player"),
+            Self::SeatsNotTaken => write!(f, "Every seat must be taken"),
+            Self::DraftNotOver => write!(f, "Units can be placed once the draft is over"),
+            Self::NoEntities => write!(f, "Deploy at least one entity"),
+            Self::NotADropTile { coords } => {
+                write!(
+                    f,
+                    "{},{} is not a drop tile of this seat",
+                    coords.x, coords.y
+                )
+            }
+            Self::ClassNotAllowed { class } => {
+                write!(f, "{:?} is not allowed for this seat", class)
+            }
+            Self::OverBudget { cost, budget } => {
+                write!(f, "Deployment costs {} points out of {}", cost, budget)
+            }
+            Self::NotDrafted { class } => {
+                write!(f, "No {:?} left among the drafted units", class)
+            }
+        }
+    }
+}
+
+/// Checks the entities fit on the seat's drop tiles, classes and points.
+pub fn validate(
+    player: &ScenarioPlayer,
+    entities: &HashMap<Coords, CharClass>,
+) -> Result<(), DeployRejection> {
+    check_drop_tiles(player, entities)?;
+    let mut cost = 0;
+    for class in entities.values() {
+        cost += player
+            .allowed_clases
+            .iter()
+            .find(|allowed| &allowed.game_class == class)
+            .ok_or(DeployRejection::ClassNotAllowed {
+                class: class.clone(),
+            })?
+            .player_points;
+    }
+    if cost > player.player_points {
+        return Err(DeployRejection::OverBudget {
+            cost,
+            budget: player.player_points,
+        });
+    }
+    Ok(())
+}
+
+/// Checks the entities fit on the seat's drop tiles, and are all among its drafted units.
+pub fn validate_drafted(
+    player: &ScenarioPlayer,
+    drafted: &[CharClass],
+    entities: &HashMap<Coords, CharClass>,
+) -> Result<(), DeployRejection> {
+    check_drop_tiles(player, entities)?;
+    let mut drafted = drafted.to_vec();
+    for class in entities.values() {
+        let i = drafted
+            .iter()
+            .position(|c| c == class)
+            .ok_or(DeployRejection::NotDrafted {
+                class: class.clone(),
+            })?;
+        drafted.remove(i);
+    }
+    Ok(())
+}
+
+fn check_drop_tiles(
+    player: &ScenarioPlayer,
+    entities: &HashMap<Coords, CharClass>,
+) -> Result<(), DeployRejection> {
+    if entities.is_empty() {
+        return Err(DeployRejection::NoEntities);
+    }
+    match entities
+        .keys()
+        .find(|coords| !player.drop_tiles.contains(coords))
+    {
+        Some(coords) => Err(DeployRejection::NotADropTile {
+            coords: coords.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::schemas::AvailableClass;
+
+    fn player() -> ScenarioPlayer {
+        ScenarioPlayer {
+            player_points: 50,
+            drop_tiles: vec![
+                Coords { x: 0, y: 0 },
+                Coords { x: 2, y: 0 },
+                Coords { x: 4, y: 0 },
+            ],
+            allowed_clases: vec![AvailableClass {
+                game_class: CharClass::Warrior,
+                player_points: 25,
+            }],
+            team: 0,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let tile = |x| Coords { x, y: 0 };
+        let player = player();
+        assert_eq!(
+            validate(&player, &HashMap::from([(tile(0), CharClass::Warrior)])),
+            Ok(())
+        );
+        assert_eq!(
+            validate(&player, &HashMap::new()),
+            Err(DeployRejection::NoEntities)
+        );
+        assert_eq!(
+            validate(&player, &HashMap::from([(tile(6), CharClass::Warrior)])),
+            Err(DeployRejection::NotADropTile { coords: tile(6) })
+        );
+        assert_eq!(
+            validate(&player, &HashMap::from([(tile(0), CharClass::Archer)])),
+            Err(DeployRejection::ClassNotAllowed {
+                class: CharClass::Archer
+            })
+        );
+        assert_eq!(
+            validate(
+                &player,
+                &HashMap::from_iter([0, 2, 4].map(|x| (tile(x), CharClass::Warrior)))
+            ),
+            Err(DeployRejection::OverBudget {
+                cost: 75,
+                budget: 50
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_drafted() {
+        let tile = |x| Coords { x, y: 0 };
+        let drafted = [CharClass::Archer, CharClass::Warrior];
+        let entities = |classes: [CharClass; 2]| {
+            HashMap::from([(tile(0), classes[0].clone()), (tile(2), classes[1].clone())])
+        };
+        assert_eq!(
+            validate_drafted(
+                &player(),
+                &drafted,
+                &entities([CharClass::Warrior, CharClass::Archer])
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            validate_drafted(
+                &player(),
+                &drafted,
+                &entities([CharClass::Archer, CharClass::Archer])
+            ),
+            Err(DeployRejection::NotDrafted {
+                class: CharClass::Archer
+            })
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Drafting armies: seats take turns banning, then picking classes, in snake order.
+
+use std::{collections::HashMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::charclasses::CharClass;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DraftRules {
+    pub pool: Vec<CharClass>,
+    /// Whether each seat picks from its own copy of the pool rather than a shared one.
+    pub mirrored: bool,
+    pub bans_per_seat: usize,
+    pub picks_per_seat: usize,
+    pub turn_time_ms: i64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DraftAction {
+    Ban,
+    Pick,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DraftTurn {
+    pub seat: i64,
+    pub action: DraftAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DraftState {
+    pub rules: DraftRules,
+    pub order: Vec<DraftTurn>,
+    /// Index of the current turn in `order`.
+    pub turn: usize,
+    /// When the current turn started, `None` until every seat is taken.
+    pub turn_started_at: Option<i64>,
+    pub bans: Vec<CharClass>,
+    pub picks: HashMap<i64, Vec<CharClass>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DraftError {
+    NotStarted,
+    Over,
+    NotYourTurn,
+    Unavailable,
+}
+
+impl fmt::Display for DraftError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotStarted => write!(f, "Draft starts once every seat is taken"),
+            Self::Over => write!(f, "Draft is over"),
+            Self::NotYourTurn => write!(f, "Not your turn to draft"),
+            Self::Unavailable => write!(f, "Class is not available"),
+        }
+    }
+}
+
+fn snake(seats: i64, rounds: usize, action: DraftAction) -> Vec<DraftTurn> {
+    (0..rounds)
+        .flat_map(|round| {
+            let seats: Vec<i64> = match round % 2 {
+                0 => (0..seats).collect(),
+                _ => (0..seats).rev().collect(),
+            };
+            seats
+                .into_iter()
+                .map(move |seat| DraftTurn { seat, action })
+        })
+        .collect()
+}
+
+impl DraftState {
+    pub fn new(rules: DraftRules, seats: usize) -> Self {
+        let mut order = snake(seats as i64, rules.bans_per_seat, DraftAction::Ban);
+        order.extend(snake(seats as i64, rules.picks_per_seat, DraftAction::Pick));
+        Self {
+            rules,
+            order,
+            turn: 0,
+            turn_started_at: None,
+            bans: vec![],
+            picks: HashMap::new(),
+        }
+    }
+    pub fn start(&mut self, now: i64) {
+        self.turn_started_at.get_or_insert(now);
+    }
+    pub fn is_over(&self) -> bool {
+        self.turn >= self.order.len()
+    }
+    pub fn current(&self) -> Option<&DraftTurn> {
+        self.turn_started_at?;
+        self.order.get(self.turn)
+    }
+    pub fn time_left(&self, now: i64) -> Option<i64> {
+        self.current()?;
+        let started_at = self.turn_started_at?;
+        Some((started_at + self.rules.turn_time_ms - now).max(0))
+    }
+    /// Classes still on offer to the seat, each ban removing one copy from every pool.
+    pub fn available(&self, seat: i64) -> Vec<CharClass> {
+        let mut pool = self.rules.pool.clone();
+        let taken = self
+            .picks
+            .iter()
+            .filter(|(index, _)| !self.rules.mirrored || **index == seat)
+            .flat_map(|(_, picks)| picks)
+            .chain(&self.bans);
+        for class in taken {
+            if let Some(i) = pool.iter().position(|c| c == class) {
+                pool.remove(i);
+            }
+        }
+        pool
+    }
+    pub fn choose(&mut self, seat: i64, class: CharClass, now: i64) -> Result<(), DraftError> {
+        let turn = match self.current() {
+            Some(turn) => turn.clone(),
+            None if self.is_over() => return Err(DraftError::Over),
+            None => return Err(DraftError::NotStarted),
+        };
+        if turn.seat != seat {
+            return Err(DraftError::NotYourTurn);
+        }
+        if !self.available(seat).contains(&class) {
+            return Err(DraftError::Unavailable);
+        }
+        match turn.action {
+            DraftAction::Ban => self.bans.push(class),
+            DraftAction::Pick => self.picks.entry(seat).or_default().push(class),
+        }
+        self.next_turn(now);
+        Ok(())
+    }
+    /// Plays the turn of a seat out of time: the ban is skipped, or the first
+    /// class available is picked.
+    pub fn timeout(&mut self, now: i64) {
+        let Some(turn) = self.current().cloned() else {
+            return;
+        };
+        if turn.action == DraftAction::Pick {
+            if let Some(class) = self.available(turn.seat).first().cloned() {
+                self.picks.entry(turn.seat).or_default().push(class);
+            }
+        }
+        self.next_turn(now);
+    }
+    fn next_turn(&mut self, now: i64) {
+        self.turn += 1;
+        self.turn_started_at = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules(mirrored: bool) -> DraftRules {
+        DraftRules {
+            pool: vec![CharClass::Warrior, CharClass::Archer, CharClass::Mage],
+            mirrored,
+            bans_per_seat: 1,
+            picks_per_seat: 2,
+            turn_time_ms: 1000,
+        }
+    }
+
+    #[test]
+    fn test_snake_order() {
+        let draft = DraftState::new(rules(false), 2);
+        let order: Vec<(i64, DraftAction)> =
+            draft.order.iter().map(|t| (t.seat, t.action)).collect();
+        assert_eq!(
+            order,
+            vec![
+                (0, DraftAction::Ban),
+                (1, DraftAction::Ban),
+                (0, DraftAction::Pick),
+                (1, DraftAction::Pick),
+                (1, DraftAction::Pick),
+                (0, DraftAction::Pick),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_choose() {
+        let mut draft = DraftState::new(rules(false), 2);
+        assert_eq!(
+            draft.choose(0, CharClass::Mage, 0),
+            Err(DraftError::NotStarted)
+        );
+        draft.start(0);
+        assert_eq!(
+            draft.choose(1, CharClass::Mage, 0),
+            Err(DraftError::NotYourTurn)
+        );
+        draft.choose(0, CharClass::Mage, 0).unwrap();
+        draft.timeout(1000);
+        draft.choose(0, CharClass::Warrior, 1000).unwrap();
+        assert_eq!(
+            draft.choose(1, CharClass::Warrior, 1000),
+            Err(DraftError::Unavailable)
+        );
+        assert_eq!(draft.available(1), vec![CharClass::Archer]);
+
+        let mut draft = DraftState::new(rules(true), 2);
+        draft.start(0);
+        draft.choose(0, CharClass::Mage, 0).unwrap();
+        draft.timeout(0);
+        draft.choose(0, CharClass::Warrior, 0).unwrap();
+        assert_eq!(
+            draft.available(1),
+            vec![CharClass::Warrior, CharClass::Archer]
+        );
+    }
+}
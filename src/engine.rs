@@ -0,0 +1,419 @@
+//! Rules of the game, free of any IO: the server, bots and tests all go through
+//! `Game::apply` and `legal_actions`.
+
+use std::{cmp::min, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abilities::{Ability, AbilityName, TargetType},
+    schemas::{ActionLog, Coords, EndReason, Entity, Game, TileType},
+    services::{get_distance, has_los},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GameAction {
+    pub entity_id: uuid::Uuid,
+    pub ability: AbilityName,
+    pub target: Coords,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum GameEvent {
+    AbilityUsed {
+        caster: uuid::Uuid,
+        ability: AbilityName,
+        target: Coords,
+    },
+    Moved {
+        entity_id: uuid::Uuid,
+        from: Coords,
+        to: Coords,
+    },
+    Damaged {
+        entity_id: uuid::Uuid,
+        amount: i64,
+    },
+    Delayed {
+        entity_id: uuid::Uuid,
+        amount: i64,
+    },
+    ResourceSpent {
+        entity_id: uuid::Uuid,
+        resource: String,
+        amount: i64,
+    },
+    TurnEnded {
+        entity_id: uuid::Uuid,
+        next_move_time: i64,
+    },
+    Died {
+        entity_id: uuid::Uuid,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RuleError {
+    NoActiveEntity,
+    NotYourTurn,
+    UnknownAbility,
+    OffMap,
+    OutOfRange,
+    NotWalkable,
+    NotAnEnnemy,
+    NotAnAlly,
+    NotSelf,
+    NoLineOfSight,
+    LackResource(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoActiveEntity => write!(f, "No active entity"),
+            Self::NotYourTurn => write!(f, "This entity is not the one to play"),
+            Self::UnknownAbility => write!(f, "This entity can't use that ability"),
+            Self::OffMap => write!(f, "Target is out of the map"),
+            Self::OutOfRange => write!(f, "Out of range"),
+            Self::NotWalkable => write!(f, "Target must be walkable"),
+            Self::NotAnEnnemy => write!(f, "Target must be an ennemy"),
+            Self::NotAnAlly => write!(f, "Target must be an ally"),
+            Self::NotSelf => write!(f, "This ability is self-cast"),
+            Self::NoLineOfSight => write!(f, "Target is out of line of sight"),
+            Self::LackResource(resource) => {
+                write!(f, "Ability is not ready, lack resource {}", resource)
+            }
+        }
+    }
+}
+
+pub fn is_valid_target(game: &Game, ability: &Ability, target: &Coords) -> Result<(), RuleError> {
+    let caster = &ability.caster;
+    let target_entity = game.entities.get(target).and_then(|v| v.first());
+    let distance = get_distance(&caster.coords, target);
+    if distance > ability.max_range(&caster.game_class) {
+        return Err(RuleError::OutOfRange);
+    }
+    let is_ally =
+        |e: &Entity| game.team(e.scenario_player_index) == game.team(caster.scenario_player_index);
+    match ability.target_type() {
+        TargetType::Walkable => {
+            if target_entity.is_some() {
+                return Err(RuleError::NotWalkable);
+            }
+            if game
+                .map
+                .get(target)
+                .is_some_and(|tile| tile.is_blocking_walk())
+            {
+                return Err(RuleError::NotWalkable);
+            }
+        }
+        TargetType::Ennemy => match target_entity {
+            Some(e) if !is_ally(e) => {}
+            Some(e) if game.friendly_fire && e.id != caster.id => {}
+            _ => return Err(RuleError::NotAnEnnemy),
+        },
+        TargetType::Ally => match target_entity {
+            Some(e) if is_ally(e) => {}
+            _ => return Err(RuleError::NotAnAlly),
+        },
+        TargetType::Selfcast => {
+            if target_entity != Some(caster) {
+                return Err(RuleError::NotSelf);
+            }
+        }
+    }
+    if ability.needs_los()
+        && has_los(
+            &caster.coords,
+            target,
+            &game.map,
+            &game.blocking_entities(caster.scenario_player_index),
+            TileType::is_blocking_sight,
+        )
+        .is_err()
+    {
+        return Err(RuleError::NoLineOfSight);
+    }
+    Ok(())
+}
+
+/// Every action the active entity may take right now.
+pub fn legal_actions(game: &Game) -> Vec<GameAction> {
+    let Ok(caster) = game.get_trait_entity() else {
+        return vec![];
+    };
+    caster
+        .game_class
+        .get_ability_list()
+        .into_iter()
+        .flat_map(|name| {
+            game.map.keys().filter_map(move |target| {
+                let action = GameAction {
+                    entity_id: caster.id,
+                    ability: name.clone(),
+                    target: target.clone(),
+                };
+                game.check(&action).ok().map(|_| action)
+            })
+        })
+        .collect()
+}
+
+impl Game {
+    /// Validates `action` without touching the game.
+    pub fn check(&self, action: &GameAction) -> Result<Ability, RuleError> {
+        let caster = self
+            .get_trait_entity()
+            .map_err(|_| RuleError::NoActiveEntity)?;
+        if caster.id != action.entity_id {
+            return Err(RuleError::NotYourTurn);
+        }
+        if !caster
+            .game_class
+            .get_ability_list()
+            .contains(&action.ability)
+        {
+            return Err(RuleError::UnknownAbility);
+        }
+        if !self.map.contains_key(&action.target) {
+            return Err(RuleError::OffMap);
+        }
+        let ability = Ability {
+            name: action.ability.clone(),
+            caster: caster.clone(),
+        };
+        is_valid_target(self, &ability, &action.target)?;
+        for (resource_name, cost) in ability.get_costs() {
+            match caster.resources.get(&resource_name) {
+                Some(resource) if resource.current >= cost => {}
+                _ => return Err(RuleError::LackResource(resource_name)),
+            }
+        }
+        Ok(ability)
+    }
+
+    /// Plays `action` for the active entity and lets time run until the next one.
+    /// The game is left untouched if the action is illegal.
+    pub fn apply(&mut self, action: &GameAction) -> Result<Vec<GameEvent>, RuleError> {
+        let ability = self.check(action)?;
+        let caster = &ability.caster;
+        let target = &action.target;
+        let mut events = vec![GameEvent::AbilityUsed {
+            caster: caster.id,
+            ability: ability.name.clone(),
+            target: target.clone(),
+        }];
+        match ability.name {
+            AbilityName::ShieldBash => {
+                let amount = (caster.game_class.get_attack_damage() as f64 * 0.8) as i64;
+                events.extend(self.damage(target, amount));
+                if let Some(target_entity) = self.entity_at_mut(target) {
+                    target_entity.next_move_time += 12;
+                    events.push(GameEvent::Delayed {
+                        entity_id: target_entity.id,
+                        amount: 12,
+                    });
+                }
+            }
+            AbilityName::Move => {
+                self.move_entity(caster.id, &caster.coords, target);
+                events.push(GameEvent::Moved {
+                    entity_id: caster.id,
+                    from: caster.coords.clone(),
+                    to: target.clone(),
+                });
+            }
+            AbilityName::Attack => {
+                events.extend(self.damage(target, caster.game_class.get_attack_damage()));
+            }
+            AbilityName::Wait => {}
+        }
+        events.extend(self.remove_dead());
+        let Some(game_caster) = self
+            .entities
+            .values_mut()
+            .flatten()
+            .find(|e| e.id == caster.id)
+        else {
+            return Ok(events);
+        };
+        for (resource_name, cost) in ability.get_costs() {
+            if let Some(resource) = game_caster.resources.get_mut(&resource_name) {
+                resource.current -= cost;
+                events.push(GameEvent::ResourceSpent {
+                    entity_id: caster.id,
+                    resource: resource_name,
+                    amount: cost,
+                });
+            }
+        }
+        game_caster.last_move_time = caster.next_move_time;
+        game_caster.next_move_time += ability.get_delay(target.clone());
+        game_caster.log.push(ActionLog {
+            turn_time: caster.last_move_time,
+            target: target.clone(),
+            action_name: ability.name.clone(),
+        });
+        events.push(GameEvent::TurnEnded {
+            entity_id: caster.id,
+            next_move_time: game_caster.next_move_time,
+        });
+
+        if let Ok(next) = self.get_trait_entity() {
+            let elapsed_time = next.next_move_time - caster.next_move_time;
+            self.increment_resources(elapsed_time);
+        }
+        self.check_result(EndReason::Elimination);
+        Ok(events)
+    }
+
+    fn remove_dead(&mut self) -> Vec<GameEvent> {
+        let mut events = vec![];
+        self.entities.retain(|_, entities| {
+            entities.retain(|e| {
+                let alive = e.resources.get("hp").is_none_or(|hp| hp.current > 0);
+                if !alive {
+                    events.push(GameEvent::Died { entity_id: e.id });
+                }
+                alive
+            });
+            !entities.is_empty()
+        });
+        events
+    }
+
+    pub fn increment_resources(&mut self, elapsed_time: i64) {
+        self.entities.values_mut().flatten().for_each(|e| {
+            e.resources
+                .values_mut()
+                .for_each(|r| r.current = min(r.current + r.per_turn * elapsed_time, r.max));
+        });
+    }
+
+    fn entity_at_mut(&mut self, coords: &Coords) -> Option<&mut Entity> {
+        self.entities.get_mut(coords).and_then(|v| v.first_mut())
+    }
+
+    fn damage(&mut self, target: &Coords, amount: i64) -> Vec<GameEvent> {
+        let Some(target_entity) = self.entity_at_mut(target) else {
+            return vec![];
+        };
+        let Some(hp) = target_entity.resources.get_mut("hp") else {
+            return vec![];
+        };
+        hp.current -= amount;
+        vec![GameEvent::Damaged {
+            entity_id: target_entity.id,
+            amount,
+        }]
+    }
+
+    fn move_entity(&mut self, entity_id: uuid::Uuid, from: &Coords, to: &Coords) {
+        let Some(tile) = self.entities.get_mut(from) else {
+            return;
+        };
+        let Some(position) = tile.iter().position(|e| e.id == entity_id) else {
+            return;
+        };
+        let mut entity = tile.remove(position);
+        if tile.is_empty() {
+            self.entities.remove(from);
+        }
+        entity.coords = to.clone();
+        self.entities.entry(to.clone()).or_default().push(entity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::charclasses::CharClass;
+
+    fn game() -> Game {
+        let mut game = Game::new();
+        for (index, x, next_move_time) in [(0, -2, 0), (1, 2, 1)] {
+            let coords = Coords { x, y: 0 };
+            game.entities.insert(
+                coords.clone(),
+                vec![Entity {
+                    user_id: format!("player{}", index),
+                    id: uuid::Uuid::new_v4(),
+                    coords,
+                    resources: HashMap::from_iter(CharClass::Warrior.get_resource_list()),
+                    scenario_player_index: index,
+                    last_move_time: 0,
+                    next_move_time,
+                    game_class: CharClass::Warrior,
+                    log: vec![],
+                    plan: None,
+                }],
+            );
+        }
+        game
+    }
+
+    #[test]
+    fn test_apply_move() {
+        let mut game = game();
+        let caster = game.get_trait_entity().unwrap().clone();
+        let target = Coords { x: 0, y: 0 };
+        assert!(legal_actions(&game).contains(&GameAction {
+            entity_id: caster.id,
+            ability: AbilityName::Move,
+            target: target.clone(),
+        }));
+        let events = game
+            .apply(&GameAction {
+                entity_id: caster.id,
+                ability: AbilityName::Move,
+                target: target.clone(),
+            })
+            .unwrap();
+        assert!(events.contains(&GameEvent::Moved {
+            entity_id: caster.id,
+            from: caster.coords.clone(),
+            to: target.clone(),
+        }));
+        assert_eq!(game.entities.get(&target).unwrap()[0].id, caster.id);
+        assert!(!game.entities.contains_key(&caster.coords));
+    }
+
+    #[test]
+    fn test_apply_rejects_bad_input() {
+        let mut game = game();
+        let before = game.clone();
+        let caster = game.get_trait_entity().unwrap().clone();
+        for (entity_id, ability, target, error) in [
+            (
+                uuid::Uuid::new_v4(),
+                AbilityName::Wait,
+                caster.coords.clone(),
+                RuleError::NotYourTurn,
+            ),
+            (
+                caster.id,
+                AbilityName::Attack,
+                Coords { x: 0, y: 0 },
+                RuleError::NotAnEnnemy,
+            ),
+            (
+                caster.id,
+                AbilityName::Move,
+                Coords { x: 40, y: 0 },
+                RuleError::OffMap,
+            ),
+        ] {
+            let action = GameAction {
+                entity_id,
+                ability,
+                target,
+            };
+            assert_eq!(game.apply(&action), Err(error));
+        }
+        assert_eq!(game, before);
+    }
+}
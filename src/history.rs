@@ -0,0 +1,186 @@
+//! Append-only record of everything accepted in a game, enough to rebuild any of
+//! its intermediate states with the rules engine.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    engine::{GameAction, RuleError},
+    schemas::{Coords, EndReason, Entity, Game, GameResult, Seat, TileType},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HistoryRecord {
+    Created {
+        seed: u64,
+        map: HashMap<Coords, TileType>,
+        #[serde(default)]
+        seats: Vec<Seat>,
+        #[serde(default)]
+        friendly_fire: bool,
+    },
+    Deployed {
+        entities: Vec<Entity>,
+    },
+    Action(GameAction),
+    Eliminated {
+        seat: i64,
+    },
+    Resigned {
+        seat: i64,
+    },
+    DrawOffered {
+        seat: i64,
+    },
+    DrawDeclined {
+        seat: i64,
+    },
+    AbortVoted {
+        seat: i64,
+    },
+    Ended(GameResult),
+}
+
+impl Game {
+    pub fn replay_record(&mut self, record: &HistoryRecord) -> Result<(), RuleError> {
+        match record {
+            HistoryRecord::Created {
+                seed,
+                map,
+                seats,
+                friendly_fire,
+            } => {
+                self.seed = *seed;
+                self.map = map.clone();
+                self.seats = seats.clone();
+                self.friendly_fire = *friendly_fire;
+            }
+            HistoryRecord::Deployed { entities } => self.deploy(entities.clone()),
+            HistoryRecord::Action(action) => {
+                self.apply(action)?;
+                self.draw_offers.clear();
+            }
+            HistoryRecord::Eliminated { seat } => {
+                self.forfeit(*seat, EndReason::Elimination);
+            }
+            HistoryRecord::Resigned { seat } => {
+                self.forfeit(*seat, EndReason::Resignation);
+            }
+            HistoryRecord::DrawOffered { seat } => {
+                self.offer_draw(*seat);
+            }
+            HistoryRecord::DrawDeclined { .. } => self.decline_draw(),
+            HistoryRecord::AbortVoted { seat } => {
+                self.vote_abort(*seat);
+            }
+            HistoryRecord::Ended(result) => self.result = Some(result.clone()),
+        }
+        Ok(())
+    }
+}
+
+/// Records up to the one before the last `delay` actions.
+pub fn delayed(records: &[HistoryRecord], delay: usize) -> &[HistoryRecord] {
+    if delay == 0 {
+        return records;
+    }
+    let actions: Vec<usize> = records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| matches!(record, HistoryRecord::Action(_)))
+        .map(|(i, _)| i)
+        .collect();
+    match actions.len().checked_sub(delay) {
+        Some(kept) => &records[..actions[kept]],
+        None => actions.first().map(|i| &records[..*i]).unwrap_or(records),
+    }
+}
+
+/// Every state the game went through, the one following `records[i]` being at index `i`.
+pub fn replay(game_id: uuid::Uuid, records: &[HistoryRecord]) -> Result<Vec<Game>, RuleError> {
+    let mut game = Game::new();
+    game.id = game_id;
+    game.map = HashMap::new();
+    let mut states = Vec::with_capacity(records.len());
+    for record in records {
+        game.replay_record(record)?;
+        states.push(game.clone());
+    }
+    Ok(states)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{abilities::AbilityName, charclasses::CharClass};
+
+    fn deployed(game: &mut Game) -> Vec<HistoryRecord> {
+        let mut records = vec![HistoryRecord::Created {
+            seed: game.seed,
+            map: game.map.clone(),
+            seats: game.seats.clone(),
+            friendly_fire: game.friendly_fire,
+        }];
+        let entities: Vec<Entity> = [(0, -2), (1, 2)]
+            .into_iter()
+            .map(|(index, x)| Entity {
+                user_id: format!("player{}", index),
+                id: uuid::Uuid::new_v4(),
+                coords: Coords { x, y: 0 },
+                resources: HashMap::from_iter(CharClass::Archer.get_resource_list()),
+                scenario_player_index: index,
+                last_move_time: 0,
+                next_move_time: index,
+                game_class: CharClass::Archer,
+                log: vec![],
+                plan: None,
+            })
+            .collect();
+        game.deploy(entities.clone());
+        records.push(HistoryRecord::Deployed { entities });
+        records
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut game = Game::new();
+        let mut records = deployed(&mut game);
+        for _ in 0..3 {
+            let action = GameAction {
+                entity_id: game.get_trait_entity().unwrap().id,
+                ability: AbilityName::Wait,
+                target: game.get_trait_entity().unwrap().coords.clone(),
+            };
+            game.apply(&action).unwrap();
+            records.push(HistoryRecord::Action(action));
+        }
+
+        let states = replay(game.id, &records).unwrap();
+        assert_eq!(states.len(), records.len());
+        assert_eq!(states.last(), Some(&game));
+    }
+
+    #[test]
+    fn test_replay_draw() {
+        let mut game = Game::new();
+        let mut records = deployed(&mut game);
+        records.extend([
+            HistoryRecord::DrawOffered { seat: 0 },
+            HistoryRecord::DrawDeclined { seat: 1 },
+            HistoryRecord::DrawOffered { seat: 1 },
+            HistoryRecord::DrawOffered { seat: 0 },
+        ]);
+
+        let states = replay(game.id, &records).unwrap();
+        assert!(states[records.len() - 3].draw_offers.is_empty());
+        assert_eq!(states[records.len() - 2].result, None);
+        assert_eq!(
+            states.last().unwrap().result,
+            Some(GameResult {
+                winners: vec![],
+                reason: EndReason::Draw
+            })
+        );
+    }
+}
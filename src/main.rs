@@ -1,5 +1,5 @@
 use axum::middleware;
-use axum::routing::{get, post};
+use axum::routing::{delete, get, post, put};
 use axum::{extract::Request, http::StatusCode, middleware::Next, response::IntoResponse, Router};
 use dashmap_cache::DashmapCache;
 use std::net::SocketAddr;
@@ -7,24 +7,42 @@ use std::sync::Arc;
 use std::time;
 use stores::database::Repository;
 use tower_sessions::Expiry;
-use tower_sessions::MemoryStore;
 use tower_sessions::SessionManagerLayer;
 
 use crate::stores::events::Events;
+use crate::stores::login_attempts::LoginAttempts;
+use crate::stores::queue::Queue;
+use crate::stores::sessions::{self, FileSessionStore};
 // pub mod config
 pub mod abilities;
+pub mod accounts;
+pub mod admin;
 pub mod charclasses;
+pub mod deployment;
+pub mod draft;
+pub mod engine;
+pub mod history;
 pub mod map;
+pub mod matchmaking;
+pub mod plans;
+pub mod profiles;
 pub mod rest;
+pub mod scenarios;
 pub mod schemas;
 pub mod services;
 pub mod stores;
+pub mod throttle;
+pub mod timeline;
+pub mod tokens;
 
 #[derive(Clone)]
 pub struct Repositories {
     pub cache: Arc<DashmapCache>,
     pub events: Events,
     pub db: Repository,
+    pub queue: Queue,
+    pub sessions: FileSessionStore,
+    pub login_attempts: LoginAttempts,
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 12)]
@@ -37,24 +55,97 @@ async fn main() {
         db: Repository::new().await,
         cache: Arc::new(DashmapCache::new()),
         events: Events::new(),
+        queue: Queue::new(),
+        sessions: FileSessionStore::load(),
+        login_attempts: LoginAttempts::new(),
     };
 
-    let session_store = MemoryStore::default();
-    let session_layer = SessionManagerLayer::new(session_store).with_expiry(Expiry::OnSessionEnd);
+    tokio::spawn(services::watch_clocks(
+        repos.db.clone(),
+        repos.events.clone(),
+    ));
+
+    tokio::spawn(services::run_matchmaking(
+        repos.db.clone(),
+        repos.queue.clone(),
+    ));
+
+    tokio::spawn(services::clean_up_guests(
+        repos.db.clone(),
+        repos.sessions.clone(),
+    ));
+
+    let session_layer = SessionManagerLayer::new(repos.sessions.clone())
+        .with_expiry(Expiry::OnInactivity(sessions::session_ttl()));
 
     let app = Router::new()
         .route("/login", post(rest::login))
         .route("/create_user", post(rest::create_user))
         .route("/logout", post(rest::logout))
+        .route("/guest", post(rest::create_guest))
+        .route("/account/upgrade", post(rest::upgrade_guest))
+        .route("/account", delete(rest::delete_account))
+        .route("/account/password", put(rest::change_password))
+        .route("/account/sessions", get(rest::list_sessions))
+        .route(
+            "/account/tokens",
+            get(rest::list_tokens).post(rest::create_token),
+        )
+        .route("/account/tokens/:token_id", delete(rest::revoke_token))
+        .route(
+            "/account/sessions/:session_id",
+            delete(rest::revoke_session),
+        )
         .route("/game", get(rest::get_active_game))
         .route("/game", post(rest::new_game))
+        .route(
+            "/user/:user_id",
+            get(rest::get_profile).put(rest::update_profile),
+        )
+        .route(
+            "/matchmaking",
+            get(rest::get_queue_status)
+                .put(rest::join_queue)
+                .delete(rest::leave_queue),
+        )
+        .route(
+            "/challenges",
+            get(rest::get_challenges).post(rest::challenge_player),
+        )
+        .route(
+            "/challenges/:challenge_id/accept",
+            post(rest::accept_challenge),
+        )
+        .route(
+            "/challenges/:challenge_id/decline",
+            post(rest::decline_challenge),
+        )
         .route("/scenario", get(rest::get_scenarios))
         .route("/scenario/:scenario_id", get(rest::get_scenario_players))
         .route(
             "/game/:game_id/scenario_players",
             get(rest::get_available_scenario_players),
         )
-        .route("/game/:game_id/deploy", post(rest::deploy_entities))
+        .route(
+            "/game/:game_id/draft",
+            get(rest::get_draft).post(rest::draft_class),
+        )
+        .route("/game/:game_id/join", post(rest::join_game))
+        .route("/game/:game_id/invite", get(rest::get_invite))
+        .route("/invite/:code", post(rest::join_by_invite))
+        .route(
+            "/game/:game_id/seat/:seat",
+            put(rest::claim_seat).delete(rest::leave_seat),
+        )
+        .route("/game/:game_id/seat/:seat/ready", put(rest::set_ready))
+        .route(
+            "/game/:game_id/seat/:seat/control",
+            post(rest::take_over_seat).delete(rest::reclaim_seat),
+        )
+        .route(
+            "/game/:game_id/deploy",
+            get(rest::get_deployment).post(rest::deploy_entities),
+        )
         .route(
             "/game/:game_id/ability/:ability_name",
             post(rest::use_ability),
@@ -63,7 +154,52 @@ async fn main() {
             "/game/:game_id/entity/transfer",
             post(rest::transfer_entity),
         )
+        .route(
+            "/game/:game_id/entity/:entity_id/controller",
+            put(rest::delegate_entity).delete(rest::reclaim_entity),
+        )
+        .route(
+            "/game/:game_id/entity/:entity_id/plan",
+            get(rest::get_plan)
+                .put(rest::set_plan)
+                .delete(rest::cancel_plan),
+        )
+        .route("/game/:game_id/handoff", post(rest::confirm_handoff))
+        .route("/game/:game_id/resign", post(rest::resign))
+        .route("/game/:game_id/draw/offer", post(rest::offer_draw))
+        .route("/game/:game_id/draw/accept", post(rest::accept_draw))
+        .route("/game/:game_id/draw/decline", post(rest::decline_draw))
+        .route("/game/:game_id/abort", post(rest::abort_game))
+        .route("/game/:game_id/replay", get(rest::get_replay))
         .route("/game/:game_id/ws", get(rest::ws_handler))
+        .route("/game/:game_id/spectate/ws", get(rest::spectate_ws_handler))
+        .route(
+            "/game/:game_id/spectating",
+            put(rest::set_spectator_settings),
+        )
+        .route("/admin/games", get(rest::admin_list_games))
+        .route(
+            "/admin/games/:game_id",
+            get(rest::admin_inspect_game).delete(rest::admin_delete_game),
+        )
+        .route(
+            "/admin/games/:game_id/finish",
+            post(rest::admin_finish_game),
+        )
+        .route("/admin/users", get(rest::admin_list_users))
+        .route(
+            "/admin/users/:user_id/password",
+            put(rest::admin_reset_password),
+        )
+        .route(
+            "/admin/users/:user_id/ban",
+            put(rest::admin_ban).delete(rest::admin_unban),
+        )
+        .route(
+            "/admin/users/:user_id/roles/:role",
+            put(rest::admin_grant_role).delete(rest::admin_revoke_role),
+        )
+        .route("/admin/audit", get(rest::admin_get_audit))
         .layer(session_layer)
         .layer(middleware::from_fn(log_access))
         .with_state(repos);
@@ -71,7 +207,12 @@ async fn main() {
     let addr = SocketAddr::from(([127, 0, 0, 1], 8061));
     tracing::debug!("listening on {addr}");
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
 
 async fn log_access(req: Request, next: Next) -> Result<impl IntoResponse, (StatusCode, String)> {
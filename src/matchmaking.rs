@@ -0,0 +1,167 @@
+//! Elo ratings and pairing of queued players.
+
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::{EndReason, GameResult};
+
+pub const DEFAULT_RATING: i64 = 1500;
+const K_FACTOR: f64 = 32.0;
+/// Rating difference accepted right after joining the queue.
+const INITIAL_RANGE: i64 = 100;
+/// The range widens by this much every `RANGE_STEP_MS`, up to `MAX_RANGE`.
+const RANGE_INCREMENT: i64 = 50;
+const RANGE_STEP_MS: i64 = 10_000;
+const MAX_RANGE: i64 = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Rating {
+    pub elo: i64,
+    pub games: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            elo: DEFAULT_RATING,
+            games: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QueueEntry {
+    pub user_id: String,
+    pub elo: i64,
+    /// Scenarios the player accepts to play, by order of preference.
+    pub scenarios: Vec<i64>,
+    pub joined_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum QueueStatus {
+    Idle,
+    Searching { since: i64, range: i64 },
+    Matched { game_id: uuid::Uuid },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pairing {
+    pub players: [String; 2],
+    pub scenario: i64,
+}
+
+/// Rating difference the player accepts after waiting `waited_ms` in the queue.
+pub fn search_range(waited_ms: i64) -> i64 {
+    (INITIAL_RANGE + waited_ms.max(0) / RANGE_STEP_MS * RANGE_INCREMENT).min(MAX_RANGE)
+}
+
+/// First two players, by time spent in the queue, within each other's range and
+/// sharing a scenario.
+pub fn find_pairing(entries: &[QueueEntry], now: i64) -> Option<Pairing> {
+    let mut entries: Vec<&QueueEntry> = entries.iter().collect();
+    entries.sort_by_key(|entry| entry.joined_at);
+    entries.iter().enumerate().find_map(|(i, first)| {
+        entries[i + 1..].iter().find_map(|second| {
+            let difference = (first.elo - second.elo).abs();
+            if difference > search_range(now - first.joined_at)
+                || difference > search_range(now - second.joined_at)
+            {
+                return None;
+            }
+            let scenario = first
+                .scenarios
+                .iter()
+                .find(|scenario| second.scenarios.contains(scenario))?;
+            Some(Pairing {
+                players: [first.user_id.clone(), second.user_id.clone()],
+                scenario: *scenario,
+            })
+        })
+    })
+}
+
+/// New Elo of every seat from its `(seat, team, elo)`, each seat facing the average
+/// rating of the other teams. Aborted games leave ratings untouched.
+pub fn updated_ratings(seats: &[(i64, i64, i64)], result: &GameResult) -> Vec<i64> {
+    seats
+        .iter()
+        .map(|(seat, team, elo)| {
+            let opponents: Vec<i64> = seats
+                .iter()
+                .filter(|(_, other, _)| other != team)
+                .map(|(_, _, elo)| *elo)
+                .collect();
+            if result.reason == EndReason::Aborted || opponents.is_empty() {
+                return *elo;
+            }
+            let opponent = opponents.iter().sum::<i64>() as f64 / opponents.len() as f64;
+            let expected = 1.0 / (1.0 + 10f64.powf((opponent - *elo as f64) / 400.0));
+            let score = match result.winners.is_empty() {
+                true => 0.5,
+                false if result.winners.contains(seat) => 1.0,
+                false => 0.0,
+            };
+            elo + (K_FACTOR * (score - expected)).round() as i64
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(user_id: &str, elo: i64, scenarios: Vec<i64>, joined_at: i64) -> QueueEntry {
+        QueueEntry {
+            user_id: user_id.to_string(),
+            elo,
+            scenarios,
+            joined_at,
+        }
+    }
+
+    #[test]
+    fn test_find_pairing() {
+        let entries = vec![
+            entry("a", 1500, vec![0], 0),
+            entry("b", 1800, vec![3, 0], 0),
+            entry("c", 1550, vec![3], 0),
+        ];
+        assert_eq!(find_pairing(&entries, 0), None);
+        let entries = vec![entries[0].clone(), entry("d", 1580, vec![3, 0], 0)];
+        assert_eq!(
+            find_pairing(&entries, 0),
+            Some(Pairing {
+                players: ["a".to_string(), "d".to_string()],
+                scenario: 0
+            })
+        );
+        let entries = vec![entries[0].clone(), entry("b", 1800, vec![0], 0)];
+        assert_eq!(find_pairing(&entries, 30_000), None);
+        assert!(find_pairing(&entries, 40_000).is_some());
+    }
+
+    #[test]
+    fn test_updated_ratings() {
+        let result = GameResult {
+            winners: vec![0],
+            reason: EndReason::Elimination,
+        };
+        assert_eq!(
+            updated_ratings(&[(0, 0, 1500), (1, 1, 1500)], &result),
+            vec![1516, 1484]
+        );
+        assert_eq!(
+            updated_ratings(&[(2, 0, 1500), (0, 1, 1500)], &result),
+            vec![1484, 1516],
+            "winners are seats, not positions"
+        );
+        let draw = GameResult {
+            winners: vec![],
+            reason: EndReason::Draw,
+        };
+        assert_eq!(
+            updated_ratings(&[(0, 0, 1500), (1, 1, 1500)], &draw),
+            vec![1500, 1500]
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+
+use crate::{
+    abilities::AbilityName,
+    engine::{legal_actions, GameAction},
+    schemas::{Entity, Game, PlanStep},
+    services::{get_distance, get_los_map},
+};
+
+enum StepOutcome {
+    Done,
+    Play { action: GameAction, done: bool },
+    Failed,
+}
+
+/// Ennemies the seat of `entity` can currently see.
+pub fn visible_ennemies(game: &Game, entity: &Entity) -> HashSet<uuid::Uuid> {
+    let blocking_entities = game.blocking_entities(entity.scenario_player_index);
+    let (los_tiles, allied_vision) = get_los_map(
+        &entity.coords,
+        &game.allied_entities(entity.scenario_player_index),
+        &game.map,
+        &blocking_entities,
+    );
+    blocking_entities
+        .into_iter()
+        .filter(|e| los_tiles.contains(&e.coords) || allied_vision.contains(&e.coords))
+        .map(|e| e.id)
+        .collect()
+}
+
+impl Game {
+    /// Next action of the active entity's plan, updating the plan accordingly.
+    /// The plan is dropped once over, or as soon as it can't be followed.
+    pub fn take_planned_action(&mut self) -> Option<GameAction> {
+        let caster = self.get_trait_entity().ok()?.clone();
+        let mut plan = caster.plan.clone()?;
+        let action = if visible_ennemies(self, &caster).is_subset(&plan.known_ennemies) {
+            loop {
+                let Some(planned) = plan.steps.first().cloned() else {
+                    break None;
+                };
+                match self.plan_step(&caster, &planned.step) {
+                    StepOutcome::Done => {
+                        plan.steps.remove(0);
+                    }
+                    StepOutcome::Play { action, done } => {
+                        if done {
+                            plan.steps.remove(0);
+                        }
+                        break Some(action);
+                    }
+                    StepOutcome::Failed if planned.or_wait => {
+                        plan.steps.remove(0);
+                        break Some(GameAction {
+                            entity_id: caster.id,
+                            ability: AbilityName::Wait,
+                            target: caster.coords.clone(),
+                        });
+                    }
+                    StepOutcome::Failed => break None,
+                }
+            }
+        } else {
+            None
+        };
+        let entity = self
+            .entities
+            .values_mut()
+            .flatten()
+            .find(|e| e.id == caster.id)?;
+        entity.plan = match action {
+            Some(_) if !plan.steps.is_empty() => Some(plan),
+            _ => None,
+        };
+        action
+    }
+
+    fn plan_step(&self, caster: &Entity, step: &PlanStep) -> StepOutcome {
+        let actions = legal_actions(self);
+        match step {
+            PlanStep::MoveTo(destination) => {
+                if &caster.coords == destination {
+                    return StepOutcome::Done;
+                }
+                let distance = get_distance(&caster.coords, destination);
+                actions
+                    .into_iter()
+                    .filter(|a| a.ability == AbilityName::Move)
+                    .map(|a| (get_distance(&a.target, destination), a))
+                    .filter(|(d, _)| *d < distance)
+                    .min_by(|(d1, _), (d2, _)| d1.total_cmp(d2))
+                    .map(|(_, action)| StepOutcome::Play {
+                        done: &action.target == destination,
+                        action,
+                    })
+                    .unwrap_or(StepOutcome::Failed)
+            }
+            PlanStep::AttackNearest => actions
+                .into_iter()
+                .filter(|a| a.ability == AbilityName::Attack)
+                .min_by(|a1, a2| {
+                    get_distance(&caster.coords, &a1.target)
+                        .total_cmp(&get_distance(&caster.coords, &a2.target))
+                })
+                .map(|action| StepOutcome::Play { action, done: true })
+                .unwrap_or(StepOutcome::Failed),
+            PlanStep::Use { ability, target } => actions
+                .into_iter()
+                .find(|a| &a.ability == ability && &a.target == target)
+                .map(|action| StepOutcome::Play { action, done: true })
+                .unwrap_or(StepOutcome::Failed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        charclasses::CharClass,
+        schemas::{Coords, Plan, PlannedAction},
+    };
+
+    fn game(known_ennemies: bool) -> Game {
+        let mut game = Game::new();
+        let ennemy = Entity {
+            user_id: "player1".to_string(),
+            id: uuid::Uuid::new_v4(),
+            coords: Coords { x: 2, y: 0 },
+            resources: HashMap::from_iter(CharClass::Warrior.get_resource_list()),
+            scenario_player_index: 1,
+            last_move_time: 0,
+            next_move_time: 30,
+            game_class: CharClass::Warrior,
+            log: vec![],
+            plan: None,
+        };
+        let steps = vec![
+            PlannedAction {
+                step: PlanStep::MoveTo(Coords { x: 0, y: 0 }),
+                or_wait: false,
+            },
+            PlannedAction {
+                step: PlanStep::AttackNearest,
+                or_wait: false,
+            },
+        ];
+        let planner = Entity {
+            user_id: "player0".to_string(),
+            id: uuid::Uuid::new_v4(),
+            coords: Coords { x: -2, y: 0 },
+            scenario_player_index: 0,
+            next_move_time: 0,
+            plan: Some(Plan {
+                steps,
+                known_ennemies: if known_ennemies {
+                    HashSet::from([ennemy.id])
+                } else {
+                    HashSet::new()
+                },
+            }),
+            ..ennemy.clone()
+        };
+        game.deploy(vec![planner, ennemy]);
+        game
+    }
+
+    #[test]
+    fn test_take_planned_action() {
+        let mut game = game(true);
+        let action = game.take_planned_action().unwrap();
+        assert_eq!(action.ability, AbilityName::Move);
+        assert_eq!(action.target, Coords { x: 0, y: 0 });
+        game.apply(&action).unwrap();
+        let action = game.take_planned_action().unwrap();
+        assert_eq!(action.ability, AbilityName::Attack);
+        assert_eq!(action.target, Coords { x: 2, y: 0 });
+        assert_eq!(game.get_trait_entity().unwrap().plan, None);
+    }
+
+    #[test]
+    fn test_new_ennemy_cancels_plan() {
+        let mut game = game(false);
+        assert_eq!(game.take_planned_action(), None);
+        assert_eq!(game.get_trait_entity().unwrap().plan, None);
+    }
+}
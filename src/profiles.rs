@@ -0,0 +1,144 @@
+//! Player statistics and match history, kept up to date as games end.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    charclasses::CharClass,
+    schemas::{EndReason, GameResult, UserData},
+};
+
+pub const MATCHES_PER_PAGE: usize = 20;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MatchOutcome {
+    Win,
+    Loss,
+    Draw,
+    Aborted,
+}
+
+impl MatchOutcome {
+    pub fn of_seat(result: &GameResult, index: i64) -> Self {
+        match result {
+            GameResult {
+                reason: EndReason::Aborted,
+                ..
+            } => Self::Aborted,
+            GameResult { winners, .. } if winners.is_empty() => Self::Draw,
+            GameResult { winners, .. } if winners.contains(&index) => Self::Win,
+            _ => Self::Loss,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MatchRecord {
+    pub game_id: uuid::Uuid,
+    pub scenario: i64,
+    pub finished_at: i64,
+    pub outcome: MatchOutcome,
+    /// Rating after the game, for rated games.
+    pub rating: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlayerStats {
+    pub games: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    /// How many units of each class the player deployed.
+    pub classes: HashMap<CharClass, u32>,
+}
+
+impl PlayerStats {
+    /// Aborted games don't count.
+    pub fn record(&mut self, outcome: MatchOutcome, classes: &[CharClass]) {
+        match outcome {
+            MatchOutcome::Win => self.wins += 1,
+            MatchOutcome::Loss => self.losses += 1,
+            MatchOutcome::Draw => self.draws += 1,
+            MatchOutcome::Aborted => return,
+        }
+        self.games += 1;
+        for class in classes {
+            *self.classes.entry(class.clone()).or_default() += 1;
+        }
+    }
+    pub fn favorite_class(&self) -> Option<CharClass> {
+        self.classes
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(class, _)| class.clone())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Profile {
+    pub id: String,
+    pub display_name: String,
+    pub created_at: i64,
+    pub rating: i64,
+    pub games: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub favorite_class: Option<CharClass>,
+    /// One page of the match history, most recent first.
+    pub matches: Vec<MatchRecord>,
+    pub page: usize,
+    pub pages: usize,
+}
+
+impl Profile {
+    pub fn from_user(user: &UserData, page: usize) -> Self {
+        Self {
+            id: user.id.clone(),
+            display_name: match user.display_name.is_empty() {
+                true => user.id.clone(),
+                false => user.display_name.clone(),
+            },
+            created_at: user.created_at,
+            rating: user.rating.elo,
+            games: user.stats.games,
+            wins: user.stats.wins,
+            losses: user.stats.losses,
+            draws: user.stats.draws,
+            favorite_class: user.stats.favorite_class(),
+            matches: user
+                .matches
+                .iter()
+                .rev()
+                .skip(page * MATCHES_PER_PAGE)
+                .take(MATCHES_PER_PAGE)
+                .cloned()
+                .collect(),
+            page,
+            pages: user.matches.len().div_ceil(MATCHES_PER_PAGE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let result = GameResult {
+            winners: vec![1],
+            reason: EndReason::Elimination,
+        };
+        let mut stats = PlayerStats::default();
+        stats.record(
+            MatchOutcome::of_seat(&result, 0),
+            &[CharClass::Archer, CharClass::Warrior, CharClass::Archer],
+        );
+        stats.record(MatchOutcome::of_seat(&result, 1), &[]);
+        stats.record(MatchOutcome::Aborted, &[CharClass::Warrior]);
+        assert_eq!((stats.games, stats.wins, stats.losses), (2, 1, 1));
+        assert_eq!(stats.favorite_class(), Some(CharClass::Archer));
+    }
+}
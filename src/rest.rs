@@ -1,65 +1,188 @@
 use crate::abilities::AbilityName;
-use crate::schemas::{Coords, DeployEntitiesRequest, Gamestate, LoginForm, UserData};
-use crate::services::{tick, ServiceError};
+use crate::admin::{FinishRequest, PasswordReset, ADMIN_ROLE};
+use crate::charclasses::CharClass;
+use crate::schemas::{
+    AccountDeletion, ChallengeRequest, Coords, DeployEntitiesRequest, Gamestate, InviteQuery,
+    LoginForm, NewGameRequest, PasswordChange, PlannedAction, ProfileQuery, ProfileUpdate,
+    QueueRequest, SeatQuery, ServerMessage, SpectateQuery, SpectatorSettings, UserData,
+};
+use crate::services::{tick, Membership, ServiceError, Viewer};
 use crate::stores::database::Repository;
 use crate::stores::events::Events;
+use crate::stores::sessions;
+use crate::tokens::{TokenRequest, TokenScope};
 use crate::{services, Repositories};
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::{FromRequestParts, Path, State};
+use axum::extract::{ConnectInfo, FromRequestParts, Path, Query, State};
+use axum::http::header::{AUTHORIZATION, RETRY_AFTER};
 use axum::http::request::Parts;
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, Method, StatusCode};
 use axum::response::{IntoResponse, Redirect};
 use axum::{async_trait, Form, Json};
 
 use futures::stream::{SplitSink, SplitStream};
 use futures::{SinkExt, StreamExt};
+use serde::Serialize;
 use tokio::time::Instant;
-use tower_sessions::Session;
+use tower_sessions::{Expiry, Session};
 
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::mpsc;
 
 pub async fn login(
     State(repo): State<Repositories>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     session: Session,
     Form(fdata): Form<LoginForm>,
 ) -> impl IntoResponse {
-    match repo.db.load_user(fdata.username.as_str()).await {
+    let address = client_address(peer, &headers);
+    match services::login(&repo.db, &repo.login_attempts, &fdata, &address).await {
         Ok(user_data) => {
-            if fdata.verify(&user_data).is_ok() {
-                session
-                    .insert("roles", &user_data.roles.clone())
-                    .await
-                    .unwrap();
-                session.insert("user", user_data.id).await.unwrap();
-                return Redirect::to("/play").into_response();
-            }
-            ServiceError::Unauthorized.into_response()
+            start_session(&session, user_data, fdata.remember).await;
+            Redirect::to("/play").into_response()
         }
         Err(error) => error.into_response(),
     }
 }
 
+/// The client's address, as forwarded by the reverse proxy when it is the peer. Only
+/// the last `X-Forwarded-For` entry, appended by the proxy itself, can be trusted.
+fn client_address(peer: SocketAddr, headers: &HeaderMap) -> String {
+    if !trusted_proxies().contains(&peer.ip()) {
+        return peer.ip().to_string();
+    }
+    headers
+        .get("x-forwarded-for")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.rsplit(',').next())
+        .map(|address| address.trim().to_string())
+        .filter(|address| !address.is_empty())
+        .unwrap_or_else(|| peer.ip().to_string())
+}
+
+/// The reverse proxies whose forwarding header is believed, from the comma separated
+/// `TRUSTED_PROXIES`. None by default.
+fn trusted_proxies() -> Vec<IpAddr> {
+    std::env::var("TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|address| address.trim().parse().ok())
+        .collect()
+}
+
 pub async fn create_user(
     State(repo): State<Repositories>,
     session: Session,
     Form(fdata): Form<LoginForm>,
 ) -> impl IntoResponse {
-    let user_data = UserData::from(fdata);
-    match repo.db.save_user(&user_data).await {
-        Ok(_) => {
-            session
-                .insert("roles", &user_data.roles.clone())
-                .await
-                .unwrap();
-            session.insert("user", user_data.id).await.unwrap();
+    let remember = fdata.remember;
+    match services::create_user(repo.db, fdata).await {
+        Ok(user_data) => {
+            start_session(&session, user_data, remember).await;
             return Redirect::to("/play").into_response();
         }
         Err(error) => error.into_response(),
     }
 }
 
+pub async fn create_guest(
+    State(repo): State<Repositories>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    session: Session,
+) -> impl IntoResponse {
+    let address = client_address(peer, &headers);
+    match services::create_guest(repo.db, &repo.login_attempts, &address).await {
+        Ok(user_data) => {
+            start_session(&session, user_data, false).await;
+            Redirect::to("/play").into_response()
+        }
+        Err(error) => error.into_response(),
+    }
+}
+
+/// Turns the guest into a full account, logging them in under a fresh session.
+pub async fn upgrade_guest(
+    State(repo): State<Repositories>,
+    session: Session,
+    user: AuthenticatedUser,
+    Form(fdata): Form<LoginForm>,
+) -> impl IntoResponse {
+    if let Err(error) = user.require_session() {
+        return error.into_response();
+    }
+    let remember = fdata.remember;
+    let result = services::upgrade_guest(repo.db, user.user_id.clone(), fdata).await;
+    match result.inspect(|_| repo.sessions.revoke_all(&user.user_id, None)) {
+        Ok(user_data) => {
+            start_session(&session, user_data, remember).await;
+            Redirect::to("/play").into_response()
+        }
+        Err(error) => error.into_response(),
+    }
+}
+
+/// Logs the user in under a fresh session id.
+async fn start_session(session: &Session, user_data: UserData, remember: bool) {
+    session.cycle_id().await.unwrap();
+    session.set_expiry(Some(Expiry::OnInactivity(sessions::ttl(remember))));
+    session.insert("remember", remember).await.unwrap();
+    session
+        .insert("roles", &user_data.roles.clone())
+        .await
+        .unwrap();
+    session
+        .insert("created_at", services::now_ms())
+        .await
+        .unwrap();
+    session.insert("user", user_data.id).await.unwrap();
+}
+
+pub async fn list_sessions(
+    State(repo): State<Repositories>,
+    session: Session,
+    user: AuthenticatedUser,
+) -> impl IntoResponse {
+    if let Err(error) = user.require_session() {
+        return error.into_response();
+    }
+    (
+        StatusCode::OK,
+        Json(repo.sessions.list(&user.user_id, session.id())),
+    )
+        .into_response()
+}
+
+pub async fn revoke_session(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(error) = user.require_session() {
+        return error.into_response();
+    }
+    match repo.sessions.revoke(&user.user_id, &session_id) {
+        true => (StatusCode::OK, Json(())).into_response(),
+        false => ServiceError::NotFound.into_response(),
+    }
+}
+
 pub struct AuthenticatedUser {
     pub user_id: String,
+    /// Scope of the API token used, `None` for a session.
+    pub scope: Option<TokenScope>,
+}
+
+impl AuthenticatedUser {
+    /// Account management is not available to API tokens.
+    fn require_session(&self) -> Result<(), ServiceError> {
+        match self.scope {
+            None => Ok(()),
+            Some(_) => Err(ServiceError::Unauthorized),
+        }
+    }
 }
 
 impl IntoResponse for ServiceError {
@@ -80,18 +203,47 @@ impl IntoResponse for ServiceError {
             services::ServiceError::Unauthorized => {
                 (StatusCode::UNAUTHORIZED, "Unauthorized".to_owned()).into_response()
             }
+            services::ServiceError::DeployRejected(rejection) => {
+                (StatusCode::BAD_REQUEST, Json(rejection)).into_response()
+            }
+            services::ServiceError::Throttled(wait_ms) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, (wait_ms + 999) / 1000)],
+                "Too many attempts".to_owned(),
+            )
+                .into_response(),
+            services::ServiceError::Banned => {
+                (StatusCode::FORBIDDEN, "Banned".to_owned()).into_response()
+            }
         }
     }
 }
 
 #[async_trait]
-impl<S> FromRequestParts<S> for AuthenticatedUser
-where
-    S: Send + Sync,
-{
+impl FromRequestParts<Repositories> for AuthenticatedUser {
     type Rejection = (StatusCode, &'static str);
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Repositories,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(header) = parts.headers.get(AUTHORIZATION) {
+            let token = header
+                .to_str()
+                .ok()
+                .and_then(|header| header.strip_prefix("Bearer "))
+                .ok_or((StatusCode::UNAUTHORIZED, "Unauthorized"))?;
+            let (user_id, scope) = services::authenticate_token(&state.db, token.trim())
+                .await
+                .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthorized"))?;
+            if scope == TokenScope::Read && parts.method != Method::GET {
+                return Err((StatusCode::FORBIDDEN, "Token is read-only"));
+            }
+            return Ok(Self {
+                user_id,
+                scope: Some(scope),
+            });
+        }
         let session = parts.extensions.get::<Session>().cloned().ok_or((
             StatusCode::INTERNAL_SERVER_ERROR,
             "Can't extract session. Is `SessionManagerLayer` enabled?",
@@ -101,7 +253,89 @@ where
             .await
             .unwrap_or(None)
             .ok_or((StatusCode::UNAUTHORIZED, "Unauthorized"))?;
-        Ok(Self { user_id })
+        // The session is only saved, and its cookie renewed, when modified.
+        let remember = session
+            .get::<bool>("remember")
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or(false);
+        let ttl = sessions::ttl(remember);
+        if session
+            .id()
+            .is_some_and(|id| state.sessions.needs_refresh(id, ttl))
+        {
+            session.set_expiry(Some(Expiry::OnInactivity(ttl)));
+        }
+        // Deleted users, or guests cleaned up, are logged out.
+        match state.db.load_user(&user_id).await {
+            Ok(user) if user.banned => Err((StatusCode::FORBIDDEN, "Banned")),
+            Ok(_) => Ok(Self {
+                user_id,
+                scope: None,
+            }),
+            Err(_) => Err((StatusCode::UNAUTHORIZED, "Unauthorized")),
+        }
+    }
+}
+
+/// A logged in user taking part in the game of the `game_id` path parameter, either
+/// seated or spectating. Everyone else is refused.
+pub struct GameMember {
+    pub user_id: String,
+    pub membership: Membership,
+}
+
+#[async_trait]
+impl FromRequestParts<Repositories> for GameMember {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Repositories,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+        let game_id = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|Path(params)| params.get("game_id")?.parse::<uuid::Uuid>().ok())
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid game id"))?;
+        match services::membership(&state.db, &game_id, &user.user_id).await {
+            Ok(membership) => Ok(Self {
+                user_id: user.user_id,
+                membership,
+            }),
+            Err(ServiceError::NotFound) => Err((StatusCode::NOT_FOUND, "Not found")),
+            Err(_) => Err((StatusCode::FORBIDDEN, "Not a member of this game")),
+        }
+    }
+}
+
+/// A logged in user holding the admin role, as currently stored rather than as it
+/// was at login.
+#[derive(Debug)]
+pub struct AdminUser {
+    pub user_id: String,
+}
+
+#[async_trait]
+impl FromRequestParts<Repositories> for AdminUser {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Repositories,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+        if user.scope.is_some() {
+            return Err((StatusCode::FORBIDDEN, "Forbidden"));
+        }
+        match state.db.load_user(&user.user_id).await {
+            Ok(data) if data.has_role(ADMIN_ROLE) => Ok(Self {
+                user_id: user.user_id,
+            }),
+            _ => Err((StatusCode::FORBIDDEN, "Forbidden")),
+        }
     }
 }
 
@@ -123,10 +357,18 @@ pub async fn get_active_game(
 
 pub async fn new_game(
     State(repo): State<Repositories>,
-    _user: AuthenticatedUser,
-    axum::extract::Json(_scenario_id): axum::extract::Json<i64>,
+    user: AuthenticatedUser,
+    axum::extract::Json(req): axum::extract::Json<NewGameRequest>,
 ) -> impl IntoResponse {
-    let result = services::new_game(repo.db).await;
+    let result = services::new_game(
+        repo.db,
+        user.user_id,
+        req.scenario_id,
+        req.time_control,
+        req.private,
+        req.hot_seat,
+    )
+    .await;
     match result {
         Ok(value) => (StatusCode::OK, Json(value)).into_response(),
         Err(error) => error.into_response(),
@@ -135,68 +377,81 @@ pub async fn new_game(
 
 pub async fn deploy_entities(
     State(repo): State<Repositories>,
-    user: AuthenticatedUser,
+    user: GameMember,
     Path(game_id): Path<uuid::Uuid>,
     axum::extract::Json(deploy_entities): axum::extract::Json<DeployEntitiesRequest>,
 ) -> impl IntoResponse {
-    let result =
-        services::deploy_entities(repo.db, repo.events, user.user_id, game_id, deploy_entities)
-            .await;
+    let result = services::deploy_entities(repo.db, user.user_id, game_id, deploy_entities).await;
     match result {
         Ok(value) => (StatusCode::OK, Json(value)).into_response(),
         Err(error) => error.into_response(),
     }
 }
 
-pub async fn get_scenarios(_user: AuthenticatedUser) -> impl IntoResponse {
-    (StatusCode::OK, Json(services::get_scenarios())).into_response()
+pub async fn get_deployment(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+    Query(query): Query<SeatQuery>,
+) -> impl IntoResponse {
+    let result = services::get_deployment(repo.db, game_id, user.user_id, query.seat).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
 }
 
-pub async fn get_scenario_players(
-    _user: AuthenticatedUser,
-    Path(_scenario_id): Path<i64>,
+pub async fn get_draft(
+    State(repo): State<Repositories>,
+    _member: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
 ) -> impl IntoResponse {
-    (StatusCode::OK, Json(services::get_scenario_players())).into_response()
+    let result = services::get_draft(repo.db, game_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
 }
 
-pub async fn get_available_scenario_players(
+pub async fn draft_class(
     State(repo): State<Repositories>,
-    _user: AuthenticatedUser,
+    user: GameMember,
     Path(game_id): Path<uuid::Uuid>,
+    axum::extract::Json(class): axum::extract::Json<CharClass>,
 ) -> impl IntoResponse {
-    let result = services::get_available_scenario_players(repo.db, game_id).await;
+    let result = services::draft_class(repo.db, repo.events, game_id, user.user_id, class).await;
     match result {
         Ok(value) => (StatusCode::OK, Json(value)).into_response(),
         Err(error) => error.into_response(),
     }
 }
 
-pub async fn transfer_entity(
+pub async fn join_game(
     State(repo): State<Repositories>,
-    user_from: AuthenticatedUser,
+    user: AuthenticatedUser,
     Path(game_id): Path<uuid::Uuid>,
-    Json(user_to): Json<String>,
+    Query(query): Query<InviteQuery>,
 ) -> impl IntoResponse {
-    let result = services::transfer_entity(repo.db, game_id, user_from.user_id, user_to).await;
+    let result = services::join_game(repo.db, repo.events, game_id, user.user_id, query.code).await;
     match result {
         Ok(value) => (StatusCode::OK, Json(value)).into_response(),
         Err(error) => error.into_response(),
     }
 }
 
-pub async fn use_ability(
+pub async fn claim_seat(
     State(repo): State<Repositories>,
     user: AuthenticatedUser,
-    Path((game_id, abilty_name)): Path<(uuid::Uuid, AbilityName)>,
-    axum::extract::Json(target): axum::extract::Json<Coords>,
+    Path((game_id, seat)): Path<(uuid::Uuid, i64)>,
+    Query(query): Query<InviteQuery>,
 ) -> impl IntoResponse {
-    let result = services::use_ability(
+    let result = services::claim_seat(
         repo.db,
         repo.events,
         game_id,
         user.user_id,
-        abilty_name,
-        target,
+        seat,
+        query.code,
     )
     .await;
     match result {
@@ -205,81 +460,805 @@ pub async fn use_ability(
     }
 }
 
-pub async fn ws_handler(
+pub async fn get_invite(
     State(repo): State<Repositories>,
-    ws: WebSocketUpgrade,
     user: AuthenticatedUser,
     Path(game_id): Path<uuid::Uuid>,
 ) -> impl IntoResponse {
-    // finalize the upgrade process by returning upgrade callback.
-    // we can customize the callback by sending additional info such as address.
-    ws.on_upgrade(move |socket| handle_socket(socket, user, repo.events, repo.db, game_id))
+    let result = services::get_invite(repo.db, game_id, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
 }
 
-/// Actual websocket statemachine (one will be spawned per connection)
-async fn handle_socket(
-    socket: WebSocket,
+pub async fn join_by_invite(
+    State(repo): State<Repositories>,
     user: AuthenticatedUser,
-    events: Events,
-    db: Repository,
-    game_id: uuid::Uuid,
-) {
-    let (sender, receiver) = mpsc::channel::<Gamestate>();
-    let register = events.register(game_id, user.user_id.clone(), sender).await;
-    if register.is_err() {
-        tracing::info!("error: {:?}", register);
-        return;
+    Path(code): Path<String>,
+) -> impl IntoResponse {
+    let result = services::join_by_invite(repo.db, repo.events, user.user_id, code).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
     }
-    match db.load_game(&game_id).await {
-        Ok(game) => {
-            let _res = tick(events.clone(), &game).await;
-            if _res.is_err() {
-                tracing::info!("error {:?}", _res.unwrap_err());
-            }
-        }
-        Err(res) => {
-            tracing::info!("error {:?}", res);
-            return;
-        }
+}
+
+pub async fn leave_seat(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, seat)): Path<(uuid::Uuid, i64)>,
+) -> impl IntoResponse {
+    let result = services::leave_seat(repo.db, game_id, user.user_id, seat).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
     }
+}
 
-    let (sock_sender, sock_receiver) = socket.split();
+pub async fn set_ready(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, seat)): Path<(uuid::Uuid, i64)>,
+    axum::extract::Json(ready): axum::extract::Json<bool>,
+) -> impl IntoResponse {
+    let result =
+        services::set_ready(repo.db, repo.events, game_id, user.user_id, seat, ready).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
 
-    tokio::spawn(read_loop(sock_receiver));
-    tokio::spawn(write_loop(sock_sender, receiver));
+pub async fn get_profile(
+    State(repo): State<Repositories>,
+    _user: AuthenticatedUser,
+    Path(user_id): Path<String>,
+    Query(query): Query<ProfileQuery>,
+) -> impl IntoResponse {
+    let result = services::get_profile(repo.db, user_id, query.page).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
 }
 
-async fn read_loop(mut receiver: SplitStream<WebSocket>) {
-    loop {
-        match receiver.next().await {
-            Some(Ok(Message::Close(_))) => {
-                tracing::info!("disconnected");
-                return;
-            }
-            Some(Err(_)) => {
-                return;
-            }
-            _ => {}
-        }
+pub async fn update_profile(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    Path(user_id): Path<String>,
+    Json(update): Json<ProfileUpdate>,
+) -> impl IntoResponse {
+    let result = match user.require_session() {
+        Ok(()) => services::update_profile(repo.db, user.user_id, user_id, update).await,
+        Err(error) => Err(error),
+    };
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
     }
 }
 
-async fn write_loop(
-    mut sock_sender: SplitSink<WebSocket, Message>,
-    game_receiver: mpsc::Receiver<Gamestate>,
-) {
-    loop {
-        let result = game_receiver.recv();
-        if result.is_err() {
-            return;
+/// Also logs out every other session of the user.
+pub async fn change_password(
+    State(repo): State<Repositories>,
+    session: Session,
+    user: AuthenticatedUser,
+    Json(change): Json<PasswordChange>,
+) -> impl IntoResponse {
+    let result = match user.require_session() {
+        Ok(()) => services::change_password(repo.db, user.user_id.clone(), change).await,
+        Err(error) => Err(error),
+    };
+    match result.map(|_| repo.sessions.revoke_all(&user.user_id, session.id())) {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn delete_account(
+    State(repo): State<Repositories>,
+    session: Session,
+    user: AuthenticatedUser,
+    Json(deletion): Json<AccountDeletion>,
+) -> impl IntoResponse {
+    let result = match user.require_session() {
+        Ok(()) => {
+            services::delete_account(repo.db, repo.queue, user.user_id.clone(), deletion).await
         }
-        let res = sock_sender
-            .send(Message::Text(
-                serde_json::to_string::<Gamestate>(&result.unwrap()).unwrap(),
-            ))
-            .await;
-        if res.is_err() {
-            return;
+        Err(error) => Err(error),
+    };
+    match result.map(|_| repo.sessions.revoke_all(&user.user_id, None)) {
+        Ok(value) => {
+            session.clear().await;
+            (StatusCode::OK, Json(value)).into_response()
         }
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn list_tokens(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+) -> impl IntoResponse {
+    let result = match user.require_session() {
+        Ok(()) => services::list_tokens(repo.db, user.user_id).await,
+        Err(error) => Err(error),
+    };
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn create_token(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    Json(req): Json<TokenRequest>,
+) -> impl IntoResponse {
+    let result = match user.require_session() {
+        Ok(()) => services::create_token(repo.db, user.user_id, req).await,
+        Err(error) => Err(error),
+    };
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn revoke_token(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    Path(token_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = match user.require_session() {
+        Ok(()) => services::revoke_token(repo.db, user.user_id, token_id).await,
+        Err(error) => Err(error),
+    };
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn join_queue(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    axum::extract::Json(req): axum::extract::Json<QueueRequest>,
+) -> impl IntoResponse {
+    let result = services::join_queue(repo.db, repo.queue, user.user_id, req.scenarios).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn leave_queue(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+) -> impl IntoResponse {
+    match services::leave_queue(repo.queue, user.user_id) {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn get_queue_status(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        Json(services::get_queue_status(repo.queue, user.user_id)),
+    )
+        .into_response()
+}
+
+pub async fn get_challenges(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+) -> impl IntoResponse {
+    let result = services::get_challenges(repo.db, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn challenge_player(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    Json(req): Json<ChallengeRequest>,
+) -> impl IntoResponse {
+    let result = services::challenge_player(repo.db, user.user_id, req).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn accept_challenge(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    Path(challenge_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::accept_challenge(repo.db, user.user_id, challenge_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn decline_challenge(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    Path(challenge_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::decline_challenge(repo.db, user.user_id, challenge_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn get_scenarios(_user: AuthenticatedUser) -> impl IntoResponse {
+    (StatusCode::OK, Json(services::get_scenarios())).into_response()
+}
+
+pub async fn get_scenario_players(
+    _user: AuthenticatedUser,
+    Path(scenario_id): Path<i64>,
+) -> impl IntoResponse {
+    match services::get_scenario_players(scenario_id) {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn get_available_scenario_players(
+    State(repo): State<Repositories>,
+    _member: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::get_available_scenario_players(repo.db, game_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn transfer_entity(
+    State(repo): State<Repositories>,
+    user_from: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+    Json(user_to): Json<String>,
+) -> impl IntoResponse {
+    let result =
+        services::transfer_entity(repo.db, repo.events, game_id, user_from.user_id, user_to).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn delegate_entity(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, entity_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Json(to): Json<String>,
+) -> impl IntoResponse {
+    let result =
+        services::delegate_entity(repo.db, repo.events, game_id, user.user_id, entity_id, to).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn reclaim_entity(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, entity_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> impl IntoResponse {
+    let result =
+        services::reclaim_entity(repo.db, repo.events, game_id, user.user_id, entity_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn take_over_seat(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, seat)): Path<(uuid::Uuid, i64)>,
+) -> impl IntoResponse {
+    let result = services::take_over_seat(repo.db, repo.events, game_id, user.user_id, seat).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn reclaim_seat(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, seat)): Path<(uuid::Uuid, i64)>,
+) -> impl IntoResponse {
+    let result = services::reclaim_seat(repo.db, repo.events, game_id, user.user_id, seat).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn confirm_handoff(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::confirm_handoff(repo.db, repo.events, game_id, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn use_ability(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, abilty_name)): Path<(uuid::Uuid, AbilityName)>,
+    axum::extract::Json(target): axum::extract::Json<Coords>,
+) -> impl IntoResponse {
+    let result = services::use_ability(
+        repo.db,
+        repo.events,
+        game_id,
+        user.user_id,
+        abilty_name,
+        target,
+    )
+    .await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn get_plan(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, entity_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> impl IntoResponse {
+    let result = services::get_plan(repo.db, game_id, entity_id, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn set_plan(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, entity_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Json(steps): Json<Vec<PlannedAction>>,
+) -> impl IntoResponse {
+    let result = services::set_plan(
+        repo.db,
+        repo.events,
+        game_id,
+        entity_id,
+        user.user_id,
+        steps,
+    )
+    .await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn cancel_plan(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path((game_id, entity_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+) -> impl IntoResponse {
+    let result = services::set_plan(
+        repo.db,
+        repo.events,
+        game_id,
+        entity_id,
+        user.user_id,
+        vec![],
+    )
+    .await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn resign(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::resign(repo.db, repo.events, game_id, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn offer_draw(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::offer_draw(repo.db, repo.events, game_id, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn accept_draw(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::accept_draw(repo.db, repo.events, game_id, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn decline_draw(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::decline_draw(repo.db, repo.events, game_id, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn abort_game(
+    State(repo): State<Repositories>,
+    user: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::abort_game(repo.db, repo.events, game_id, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn get_replay(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::get_replay(repo.db, game_id, user.user_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn ws_handler(
+    State(repo): State<Repositories>,
+    ws: WebSocketUpgrade,
+    user: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    // finalize the upgrade process by returning upgrade callback.
+    // we can customize the callback by sending additional info such as address.
+    ws.on_upgrade(move |socket| async move {
+        match user.membership {
+            Membership::Seat(_) => {
+                handle_socket(socket, user.user_id, repo.events, repo.db, game_id).await
+            }
+            Membership::Spectator => {
+                handle_spectator_socket(
+                    socket,
+                    user.user_id,
+                    repo.events,
+                    repo.db,
+                    game_id,
+                    Viewer::Omniscient,
+                )
+                .await
+            }
+        }
+    })
+}
+
+pub async fn set_spectator_settings(
+    State(repo): State<Repositories>,
+    user: AuthenticatedUser,
+    Path(game_id): Path<uuid::Uuid>,
+    Json(settings): Json<SpectatorSettings>,
+) -> impl IntoResponse {
+    let result =
+        services::set_spectator_settings(repo.db, repo.events, game_id, user.user_id, settings)
+            .await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn spectate_ws_handler(
+    State(repo): State<Repositories>,
+    ws: WebSocketUpgrade,
+    user: GameMember,
+    Path(game_id): Path<uuid::Uuid>,
+    Query(query): Query<SpectateQuery>,
+) -> impl IntoResponse {
+    if let Membership::Seat(_) = user.membership {
+        return (
+            StatusCode::FORBIDDEN,
+            "Players can't spectate their own game",
+        )
+            .into_response();
+    }
+    let viewer = match query.seat {
+        Some(seat) => Viewer::Seat(seat),
+        None => Viewer::Omniscient,
+    };
+    ws.on_upgrade(move |socket| {
+        handle_spectator_socket(socket, user.user_id, repo.events, repo.db, game_id, viewer)
+    })
+}
+
+async fn handle_spectator_socket(
+    socket: WebSocket,
+    user_id: String,
+    events: Events,
+    db: Repository,
+    game_id: uuid::Uuid,
+    viewer: Viewer,
+) {
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel::<Gamestate>();
+    let res = services::spectate(db, events.clone(), game_id, user_id, viewer, sender).await;
+    let spectator = match res {
+        Ok(spectator) => spectator,
+        Err(error) => {
+            tracing::info!("error {:?}", error);
+            return;
+        }
+    };
+    let (sock_sender, sock_receiver) = socket.split();
+
+    tokio::spawn(async move {
+        read_loop(sock_receiver).await;
+        events.remove_spectator(game_id, spectator);
+    });
+    tokio::spawn(spectator_write_loop(sock_sender, receiver));
+}
+
+/// Same as `write_loop`, without blocking a worker while waiting.
+async fn spectator_write_loop(
+    mut sock_sender: SplitSink<WebSocket, Message>,
+    mut receiver: tokio::sync::mpsc::UnboundedReceiver<Gamestate>,
+) {
+    while let Some(gamestate) = receiver.recv().await {
+        let message = Message::Text(serde_json::to_string(&gamestate).unwrap());
+        if sock_sender.send(message).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Actual websocket statemachine (one will be spawned per connection)
+async fn handle_socket(
+    socket: WebSocket,
+    user_id: String,
+    events: Events,
+    db: Repository,
+    game_id: uuid::Uuid,
+) {
+    let (sender, receiver) = mpsc::channel::<ServerMessage>();
+    let register = events.register(game_id, user_id.clone(), sender).await;
+    if register.is_err() {
+        tracing::info!("error: {:?}", register);
+        return;
+    }
+    match db.load_game(&game_id).await {
+        Ok(game) if !game.draft_is_over() => services::push_draft(&events, &game).await,
+        Ok(game) => {
+            let _res = tick(events.clone(), &game).await;
+            if _res.is_err() {
+                tracing::info!("error {:?}", _res.unwrap_err());
+            }
+        }
+        Err(res) => {
+            tracing::info!("error {:?}", res);
+            events.disconnect(game_id, user_id);
+            return;
+        }
+    }
+
+    let (sock_sender, sock_receiver) = socket.split();
+
+    tokio::spawn(async move {
+        read_loop(sock_receiver).await;
+        events.disconnect(game_id, user_id);
+    });
+    tokio::spawn(write_loop(sock_sender, receiver));
+}
+
+async fn read_loop(mut receiver: SplitStream<WebSocket>) {
+    loop {
+        match receiver.next().await {
+            Some(Ok(Message::Close(_))) => {
+                tracing::info!("disconnected");
+                return;
+            }
+            Some(Err(_)) | None => {
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn write_loop<T: Serialize>(
+    mut sock_sender: SplitSink<WebSocket, Message>,
+    game_receiver: mpsc::Receiver<T>,
+) {
+    loop {
+        let result = game_receiver.recv();
+        if result.is_err() {
+            return;
+        }
+        let res = sock_sender
+            .send(Message::Text(
+                serde_json::to_string::<T>(&result.unwrap()).unwrap(),
+            ))
+            .await;
+        if res.is_err() {
+            return;
+        }
+    }
+}
+
+pub async fn admin_list_games(
+    State(repo): State<Repositories>,
+    _admin: AdminUser,
+) -> impl IntoResponse {
+    let result = services::admin_list_games(repo.db).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_inspect_game(
+    State(repo): State<Repositories>,
+    _admin: AdminUser,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::admin_inspect_game(repo.db, game_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_finish_game(
+    State(repo): State<Repositories>,
+    admin: AdminUser,
+    Path(game_id): Path<uuid::Uuid>,
+    Json(req): Json<FinishRequest>,
+) -> impl IntoResponse {
+    let result =
+        services::admin_finish_game(repo.db, repo.events, admin.user_id, game_id, req.winners)
+            .await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_delete_game(
+    State(repo): State<Repositories>,
+    admin: AdminUser,
+    Path(game_id): Path<uuid::Uuid>,
+) -> impl IntoResponse {
+    let result = services::admin_delete_game(repo.db, admin.user_id, game_id).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_list_users(
+    State(repo): State<Repositories>,
+    _admin: AdminUser,
+) -> impl IntoResponse {
+    let result = services::admin_list_users(repo.db).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_reset_password(
+    State(repo): State<Repositories>,
+    admin: AdminUser,
+    Path(user_id): Path<String>,
+    Json(req): Json<PasswordReset>,
+) -> impl IntoResponse {
+    let result =
+        services::admin_reset_password(repo.db, admin.user_id, user_id.clone(), req.password).await;
+    match result.map(|_| repo.sessions.revoke_all(&user_id, None)) {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_ban(
+    State(repo): State<Repositories>,
+    admin: AdminUser,
+    Path(user_id): Path<String>,
+) -> impl IntoResponse {
+    let result = services::admin_set_banned(repo.db, admin.user_id, user_id.clone(), true).await;
+    let result = result.inspect(|_| repo.sessions.revoke_all(&user_id, None));
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_unban(
+    State(repo): State<Repositories>,
+    admin: AdminUser,
+    Path(user_id): Path<String>,
+) -> impl IntoResponse {
+    let result = services::admin_set_banned(repo.db, admin.user_id, user_id, false).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_grant_role(
+    State(repo): State<Repositories>,
+    admin: AdminUser,
+    Path((user_id, role)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let result = services::admin_set_role(repo.db, admin.user_id, user_id, role, true).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_revoke_role(
+    State(repo): State<Repositories>,
+    admin: AdminUser,
+    Path((user_id, role)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let result = services::admin_set_role(repo.db, admin.user_id, user_id, role, false).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn admin_get_audit(
+    State(repo): State<Repositories>,
+    _admin: AdminUser,
+) -> impl IntoResponse {
+    let result = services::admin_get_audit(repo.db).await;
+    match result {
+        Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+        Err(error) => error.into_response(),
     }
 }
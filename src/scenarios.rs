@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use crate::{
+    charclasses::CharClass,
+    draft::DraftRules,
+    map,
+    schemas::{AvailableClass, Coords, ScenarioPlayer, TileType},
+};
+
+pub struct Scenario {
+    pub id: i64,
+    pub map: HashMap<Coords, TileType>,
+    pub players: Vec<ScenarioPlayer>,
+    pub friendly_fire: bool,
+    /// Armies are drafted instead of bought with points when set.
+    pub draft: Option<DraftRules>,
+}
+
+const ARENA: &str = "  ...\n ....\n .....\n..gg..\n..g#g..\n..gg..\n .....\n ....\n  ...";
+
+fn allowed_classes() -> Vec<AvailableClass> {
+    vec![
+        AvailableClass {
+            player_points: 25,
+            game_class: CharClass::Archer,
+        },
+        AvailableClass {
+            player_points: 25,
+            game_class: CharClass::Warrior,
+        },
+    ]
+}
+
+fn player(team: i64, player_points: i64, drop_tiles: Vec<(i64, i64)>) -> ScenarioPlayer {
+    ScenarioPlayer {
+        player_points,
+        drop_tiles: drop_tiles
+            .into_iter()
+            .map(|(x, y)| Coords { x, y })
+            .collect(),
+        allowed_clases: allowed_classes(),
+        team,
+    }
+}
+
+pub fn get_scenario(scenario_id: i64) -> Option<Scenario> {
+    match scenario_id {
+        0 => Some(Scenario {
+            id: 0,
+            map: HashMap::from([
+                (Coords { x: -2, y: 0 }, TileType::Floor),
+                (Coords { x: 0, y: 0 }, TileType::Floor),
+                (Coords { x: 2, y: 0 }, TileType::Floor),
+            ]),
+            players: vec![player(0, 100, vec![(-2, 0)]), player(1, 100, vec![(2, 0)])],
+            friendly_fire: false,
+            draft: None,
+        }),
+        // Two against two, teammates sharing their vision
+        1 => Some(Scenario {
+            id: 1,
+            map: map::from_text(ARENA),
+            players: vec![
+                player(0, 50, vec![(4, 0), (6, 0)]),
+                player(0, 50, vec![(0, 4), (1, 3)]),
+                player(1, 50, vec![(4, 8), (6, 8)]),
+                player(1, 50, vec![(12, 4), (11, 5)]),
+            ],
+            friendly_fire: false,
+            draft: None,
+        }),
+        // Every player for themselves
+        2 => Some(Scenario {
+            id: 2,
+            map: map::from_text(ARENA),
+            players: vec![
+                player(0, 50, vec![(4, 0), (6, 0)]),
+                player(1, 50, vec![(0, 4), (1, 5)]),
+                player(2, 50, vec![(12, 4), (11, 3)]),
+            ],
+            friendly_fire: true,
+            draft: None,
+        }),
+        // One against one, drafting from mirrored pools
+        3 => Some(Scenario {
+            id: 3,
+            map: map::from_text(ARENA),
+            players: vec![
+                player(0, 0, vec![(4, 0), (6, 0), (2, 2)]),
+                player(1, 0, vec![(4, 8), (6, 8), (8, 6)]),
+            ],
+            friendly_fire: false,
+            draft: Some(DraftRules {
+                pool: (0..3)
+                    .flat_map(|_| [CharClass::Warrior, CharClass::Archer])
+                    .collect(),
+                mirrored: true,
+                bans_per_seat: 1,
+                picks_per_seat: 3,
+                turn_time_ms: 30_000,
+            }),
+        }),
+        _ => None,
+    }
+}
+
+pub fn get_scenario_ids() -> Vec<i64> {
+    (0..)
+        .map_while(|id| get_scenario(id).map(|s| s.id))
+        .collect()
+}
@@ -1,5 +1,4 @@
 use std::{
-    cmp::min,
     collections::{HashMap, HashSet},
     str::FromStr,
 };
@@ -8,12 +7,18 @@ use serde::{Deserialize, Serialize, Serializer};
 use uuid;
 
 use crate::{
-    abilities::{Ability, AbilityName},
+    abilities::AbilityName,
     charclasses::CharClass,
-    services::ServiceError,
+    deployment::DEPLOYMENT_TIME_MS,
+    draft::DraftState,
+    matchmaking::Rating,
+    profiles::{MatchRecord, PlayerStats},
+    scenarios::Scenario,
+    services::{now_ms, ServiceError},
+    tokens::ApiToken,
 };
 use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
-use password_hash::rand_core::OsRng;
+use password_hash::rand_core::{OsRng, RngCore};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct GameRef {
@@ -21,6 +26,21 @@ pub struct GameRef {
     pub seated_players: Vec<String>,
     pub status: GameStatus,
     pub scenario: i64,
+    #[serde(default)]
+    pub result: Option<GameResult>,
+    #[serde(default)]
+    pub seats: Vec<Seat>,
+    #[serde(default)]
+    pub deployment_deadline: Option<i64>,
+    #[serde(default)]
+    pub drafting: bool,
+    #[serde(default)]
+    pub creator: String,
+    /// Code required to join, hiding the game from listings.
+    #[serde(default)]
+    pub invite_code: Option<String>,
+    #[serde(default)]
+    pub hot_seat: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
@@ -28,12 +48,116 @@ pub struct Game {
     pub id: uuid::Uuid,
     pub entities: HashMap<Coords, Vec<Entity>>,
     pub map: HashMap<Coords, TileType>,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub time_control: TimeControl,
+    #[serde(default)]
+    pub turn_started_at: i64,
+    #[serde(default)]
+    pub timeouts: HashMap<i64, u32>,
+    #[serde(default)]
+    pub clocks: HashMap<i64, i64>,
+    #[serde(default)]
+    pub result: Option<GameResult>,
+    #[serde(default)]
+    pub creator: String,
+    #[serde(default)]
+    pub spectating: SpectatorSettings,
+    #[serde(default)]
+    pub seats: Vec<Seat>,
+    #[serde(default)]
+    pub friendly_fire: bool,
+    /// Seats currently agreeing on a draw.
+    #[serde(default)]
+    pub draw_offers: HashSet<i64>,
+    /// Seats asking to abort the game.
+    #[serde(default)]
+    pub abort_votes: HashSet<i64>,
+    /// Placements of each seat, kept off the map until they are all revealed together.
+    #[serde(default)]
+    pub deployments: HashMap<i64, Vec<Entity>>,
+    #[serde(default)]
+    pub deployment_deadline: Option<i64>,
+    #[serde(default)]
+    pub draft: Option<DraftState>,
+    /// Whether the result updates the players' ratings.
+    #[serde(default)]
+    pub rated: bool,
+    /// Whether one user may take several seats and play them on one device.
+    #[serde(default)]
+    pub hot_seat: bool,
+    /// Seat whose view was last handed the device in a hot-seat game.
+    #[serde(default)]
+    pub shown_seat: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Seat {
+    pub team: i64,
+    /// User who took the seat, the only one allowed to deploy and play for it.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub ready: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct SpectatorSettings {
+    pub allowed: bool,
+    /// How many actions behind the game spectators are kept, to prevent ghosting.
+    pub delay: usize,
+}
+
+impl Default for SpectatorSettings {
+    /// Spectators are kept a few actions behind unless the creator says otherwise, so
+    /// a second account can't relay the live game.
+    fn default() -> Self {
+        Self {
+            allowed: true,
+            delay: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub enum TimeControl {
+    #[default]
+    Unlimited,
+    /// Plays `default_ability` for whoever lets `limit_ms` run out,
+    /// and forfeits the seat after `max_timeouts` timeouts in a row.
+    PerTurn {
+        limit_ms: i64,
+        default_ability: AbilityName,
+        max_timeouts: u32,
+    },
+    /// Each seat has `bank_ms` to play the whole game, plus `increment_ms` per action.
+    ChessClock { bank_ms: i64, increment_ms: i64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct GameResult {
+    pub winners: Vec<i64>,
+    pub reason: EndReason,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub enum EndReason {
+    Elimination,
+    Timeout,
+    OutOfTime,
+    Resignation,
+    Draw,
+    Aborted,
+    /// Settled by an admin.
+    Adjudicated,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub enum GameStatus {
     Running,
     Open,
+    Finished,
 }
 
 impl Game {
@@ -75,87 +199,266 @@ impl Game {
             id: uuid::Uuid::new_v4(),
             entities: HashMap::new(),
             map: map,
+            seed: OsRng.next_u64(),
+            time_control: TimeControl::Unlimited,
+            turn_started_at: 0,
+            timeouts: HashMap::new(),
+            clocks: HashMap::new(),
+            result: None,
+            creator: String::new(),
+            spectating: SpectatorSettings::default(),
+            seats: vec![],
+            friendly_fire: false,
+            draw_offers: HashSet::new(),
+            abort_votes: HashSet::new(),
+            deployments: HashMap::new(),
+            deployment_deadline: None,
+            draft: None,
+            rated: false,
+            hot_seat: false,
+            shown_seat: None,
         }
     }
-    pub fn blocking_entities(&self, index: i64) -> Vec<&Entity> {
-        self.entities
-            .values()
-            .flatten()
-            .filter(|e| e.scenario_player_index != index)
-            .collect()
+    pub fn from_scenario(scenario: &Scenario) -> Self {
+        Self {
+            map: scenario.map.clone(),
+            seats: scenario
+                .players
+                .iter()
+                .map(|p| Seat {
+                    team: p.team,
+                    user_id: None,
+                    ready: false,
+                })
+                .collect(),
+            friendly_fire: scenario.friendly_fire,
+            draft: scenario
+                .draft
+                .clone()
+                .map(|rules| DraftState::new(rules, scenario.players.len())),
+            ..Self::new()
+        }
     }
-    pub fn allied_entities(&self, index: i64) -> Vec<&Entity> {
+    /// Team of the seat, games without teams having one per seat.
+    pub fn team(&self, index: i64) -> i64 {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| self.seats.get(i))
+            .map(|seat| seat.team)
+            .unwrap_or(index)
+    }
+    pub fn team_seats(&self, team: i64) -> Vec<i64> {
+        let mut seats: Vec<i64> = match self.seats.len() {
+            0 => self.seats_in_play().into_iter().collect(),
+            len => (0..len as i64).collect(),
+        };
+        seats.retain(|index| self.team(*index) == team);
+        seats.sort();
+        seats
+    }
+    /// Seats which still have entities on the map.
+    pub fn seats_in_play(&self) -> HashSet<i64> {
         self.entities
             .values()
             .flatten()
-            .filter(|e| e.scenario_player_index == index)
+            .map(|e| e.scenario_player_index)
             .collect()
     }
-
-    pub fn increment_resources(&mut self, elapsed_time: i64) {
-        self.entities.values_mut().flatten().for_each(|e| {
-            e.resources
-                .values_mut()
-                .for_each(|r| r.current = min(r.current + r.per_turn * elapsed_time, r.max));
+    /// Removes the seat's entities, ending the game with `reason` if a single team is left.
+    pub fn forfeit(&mut self, index: i64, reason: EndReason) -> Option<GameResult> {
+        self.entities.retain(|_, entities| {
+            entities.retain(|e| e.scenario_player_index != index);
+            !entities.is_empty()
         });
+        self.check_result(reason)
     }
-
-    pub fn apply_ability(&mut self, ability: &Ability, target: &Coords) {
-        match ability.name {
-            AbilityName::ShieldBash => {
-                let target_entity: &mut Entity =
-                    self.entities.get_mut(target).unwrap().get_mut(0).unwrap();
-                target_entity.resources.get_mut("hp").unwrap().current -=
-                    (ability.caster.game_class.get_attack_damage() as f64 * 0.8) as i64;
-                target_entity.next_move_time += 12;
+    /// Once no more than one team has entities left, it wins the game.
+    pub fn check_result(&mut self, reason: EndReason) -> Option<GameResult> {
+        if self.result.is_some() {
+            return self.result.clone();
+        }
+        let teams: HashSet<i64> = self
+            .seats_in_play()
+            .into_iter()
+            .map(|index| self.team(index))
+            .collect();
+        if teams.len() > 1 {
+            return None;
+        }
+        let winners = teams
+            .into_iter()
+            .next()
+            .map(|team| self.team_seats(team))
+            .unwrap_or_default();
+        self.result = Some(GameResult { winners, reason });
+        self.result.clone()
+    }
+    /// Adds the seat to those agreeing on a draw, which is reached once every seat in play agrees.
+    pub fn offer_draw(&mut self, index: i64) -> Option<GameResult> {
+        self.draw_offers.insert(index);
+        self.check_agreement(&self.draw_offers.clone(), EndReason::Draw)
+    }
+    pub fn decline_draw(&mut self) {
+        self.draw_offers.clear();
+    }
+    /// Adds the seat to those asking to abort, which happens once every seat in play asks.
+    pub fn vote_abort(&mut self, index: i64) -> Option<GameResult> {
+        self.abort_votes.insert(index);
+        self.check_agreement(&self.abort_votes.clone(), EndReason::Aborted)
+    }
+    fn check_agreement(&mut self, votes: &HashSet<i64>, reason: EndReason) -> Option<GameResult> {
+        if self.result.is_some() || !self.seats_in_play().is_subset(votes) {
+            return None;
+        }
+        self.result = Some(GameResult {
+            winners: vec![],
+            reason,
+        });
+        self.result.clone()
+    }
+    /// Remaining time for the active entity to play, if the game is timed.
+    pub fn turn_time_left(&self, now: i64) -> Option<i64> {
+        match self.time_control {
+            TimeControl::Unlimited => None,
+            TimeControl::PerTurn { limit_ms, .. } => {
+                Some((self.turn_started_at + limit_ms - now).max(0))
             }
-            AbilityName::Move => {
-                self.entities
-                    .get_mut(&ability.caster.coords)
-                    .unwrap()
-                    .into_iter()
-                    .find(|e| e == &&ability.caster)
-                    .unwrap()
-                    .coords = target.clone();
-                let mut new_entities = HashMap::new();
-                self.entities.values_mut().flatten().for_each(|e| {
-                    match new_entities.get_mut(&e.coords) {
-                        None => {
-                            new_entities.insert(e.coords.clone(), vec![e.clone()]);
-                        }
-                        Some(vector) => vector.push(e.clone()),
-                    };
-                });
-                self.entities = new_entities;
+            TimeControl::ChessClock { .. } => {
+                let index = self.get_trait_entity().ok()?.scenario_player_index;
+                self.clocks_at(now).get(&index).copied()
             }
-            AbilityName::Attack => {
-                let target_entity: &mut Entity =
-                    self.entities.get_mut(target).unwrap().get_mut(0).unwrap();
-                target_entity.resources.get_mut("hp").unwrap().current -=
-                    ability.caster.game_class.get_attack_damage();
+        }
+    }
+    /// Time banks of every seat, the active one being charged up to `now`.
+    pub fn clocks_at(&self, now: i64) -> HashMap<i64, i64> {
+        let TimeControl::ChessClock { bank_ms, .. } = self.time_control else {
+            return HashMap::new();
+        };
+        let active = self
+            .get_trait_entity()
+            .ok()
+            .map(|e| e.scenario_player_index);
+        self.seats_in_play()
+            .into_iter()
+            .map(|index| {
+                let mut bank = self.clocks.get(&index).copied().unwrap_or(bank_ms);
+                if Some(index) == active {
+                    bank = (bank - (now - self.turn_started_at)).max(0);
+                }
+                (index, bank)
+            })
+            .collect()
+    }
+    /// Charges the active seat for the time it took to play and credits the increment.
+    /// Returns false, leaving the clock untouched, if its bank has run out.
+    pub fn charge_clock(&mut self, now: i64) -> bool {
+        let TimeControl::ChessClock { increment_ms, .. } = self.time_control else {
+            return true;
+        };
+        let Ok(index) = self.get_trait_entity().map(|e| e.scenario_player_index) else {
+            return true;
+        };
+        match self.clocks_at(now).get(&index) {
+            Some(bank) if *bank > 0 => {
+                self.clocks.insert(index, bank + increment_ms);
+                true
             }
-            AbilityName::Wait => {}
+            _ => false,
         }
-        let game_caster = self
-            .entities
-            .values_mut()
-            .flatten()
-            .find(|e| e.id == ability.caster.id)
-            .unwrap();
-        for (resource_name, cost) in ability.get_costs() {
-            game_caster
-                .resources
-                .get_mut(&resource_name)
-                .unwrap()
-                .current -= cost;
+    }
+    /// Seat taken by the user, if any.
+    pub fn seat_of(&self, user_id: &str) -> Option<i64> {
+        self.seats
+            .iter()
+            .position(|seat| seat.user_id.as_deref() == Some(user_id))
+            .map(|index| index as i64)
+    }
+    /// Every seat taken by the user, several only in hot-seat games.
+    pub fn seats_of(&self, user_id: &str) -> Vec<i64> {
+        (0..self.seats.len() as i64)
+            .filter(|index| self.seat_owner(*index) == Some(user_id))
+            .collect()
+    }
+    /// Seat that must be handed the device before its turn is shown, when the
+    /// user playing it holds other seats too.
+    pub fn awaiting_handoff(&self) -> Option<i64> {
+        if !self.hot_seat {
+            return None;
         }
-        game_caster.last_move_time = ability.caster.next_move_time;
-        game_caster.next_move_time += ability.get_delay(target.clone());
-        game_caster.log.push(ActionLog {
-            turn_time: ability.caster.last_move_time,
-            target: target.clone(),
-            action_name: ability.name.clone(),
-        });
+        let to_play = self.get_trait_entity().ok()?;
+        let seat = to_play.scenario_player_index;
+        (self.shown_seat != Some(seat) && self.shares_device(&to_play.user_id)).then_some(seat)
+    }
+    /// Whether the user plays several seats of this hot-seat game on one device.
+    pub fn shares_device(&self, user_id: &str) -> bool {
+        self.hot_seat && self.seats_of(user_id).len() > 1
+    }
+    /// User who took the seat, and whom its units answer to by default.
+    pub fn seat_owner(&self, index: i64) -> Option<&str> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| self.seats.get(i))
+            .and_then(|seat| seat.user_id.as_deref())
+    }
+    pub fn all_seats_taken(&self) -> bool {
+        self.seats.iter().all(|seat| seat.user_id.is_some())
+    }
+    pub fn all_seats_ready(&self) -> bool {
+        self.seats
+            .iter()
+            .all(|seat| seat.user_id.is_some() && seat.ready)
+    }
+    pub fn has_started(&self) -> bool {
+        self.result.is_some() || !self.entities.is_empty()
+    }
+    /// Once every seat is taken, the draft if any starts, then the deployment timer.
+    pub fn update_phase(&mut self, now: i64) {
+        let all_seats_taken = self.all_seats_taken();
+        if let Some(draft) = &mut self.draft {
+            if all_seats_taken {
+                draft.start(now);
+            }
+        }
+        self.deployment_deadline = match self.deployment_deadline {
+            _ if !all_seats_taken || !self.draft_is_over() => None,
+            None => Some(now + DEPLOYMENT_TIME_MS),
+            deadline => deadline,
+        };
+    }
+    pub fn draft_is_over(&self) -> bool {
+        self.draft.as_ref().is_none_or(|draft| draft.is_over())
+    }
+    /// Moves every seat's placements onto the map at once, returning them by seat.
+    pub fn reveal_deployments(&mut self) -> Vec<Vec<Entity>> {
+        let mut deployments: Vec<(i64, Vec<Entity>)> = self.deployments.drain().collect();
+        deployments.sort_by_key(|(index, _)| *index);
+        self.deployment_deadline = None;
+        deployments
+            .into_iter()
+            .map(|(_, entities)| {
+                self.deploy(entities.clone());
+                entities
+            })
+            .collect()
+    }
+    pub fn deploy(&mut self, entities: Vec<Entity>) {
+        for entity in entities {
+            self.entities.insert(entity.coords.clone(), vec![entity]);
+        }
+    }
+    pub fn blocking_entities(&self, index: i64) -> Vec<&Entity> {
+        self.entities
+            .values()
+            .flatten()
+            .filter(|e| self.team(e.scenario_player_index) != self.team(index))
+            .collect()
+    }
+    pub fn allied_entities(&self, index: i64) -> Vec<&Entity> {
+        self.entities
+            .values()
+            .flatten()
+            .filter(|e| self.team(e.scenario_player_index) == self.team(index))
+            .collect()
     }
 }
 
@@ -164,33 +467,95 @@ pub struct UserData {
     pub roles: Vec<String>,
     pub id: String,
     pub passhash: String,
+    #[serde(default)]
+    pub rating: Rating,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub created_at: i64,
+    #[serde(default)]
+    pub stats: PlayerStats,
+    #[serde(default)]
+    pub matches: Vec<MatchRecord>,
+    /// Pending challenges the user sent or received.
+    #[serde(default)]
+    pub challenges: Vec<Challenge>,
+    #[serde(default)]
+    pub banned: bool,
+    #[serde(default)]
+    pub tokens: Vec<ApiToken>,
+    /// Guests have a generated id and no password until they upgrade.
+    #[serde(default)]
+    pub guest: bool,
+    #[serde(default)]
+    pub last_seen_at: i64,
+    /// The name an upgraded guest logs in with, their id staying the generated one.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Set on the stub reserving an upgraded guest's name, to the guest's id.
+    #[serde(default)]
+    pub alias_of: Option<String>,
 }
 
-impl From<LoginForm> for UserData {
-    fn from(value: LoginForm) -> Self {
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let password_hash = argon2
-            .hash_password(value.password.as_bytes(), &salt)
-            .unwrap()
-            .to_string();
+impl UserData {
+    /// A user no password matches until one is set.
+    pub fn new(id: String) -> Self {
         Self {
             roles: vec![],
-            id: value.username,
-            passhash: password_hash,
+            id,
+            passhash: String::new(),
+            rating: Rating::default(),
+            display_name: String::new(),
+            created_at: now_ms(),
+            stats: PlayerStats::default(),
+            matches: vec![],
+            challenges: vec![],
+            banned: false,
+            tokens: vec![],
+            guest: false,
+            last_seen_at: now_ms(),
+            username: None,
+            alias_of: None,
         }
     }
+    pub fn set_password(&mut self, password: &str) {
+        let salt = SaltString::generate(&mut OsRng);
+        self.passhash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+    }
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+    pub fn login_name(&self) -> &str {
+        self.username.as_deref().unwrap_or(&self.id)
+    }
+}
+
+impl From<LoginForm> for UserData {
+    fn from(value: LoginForm) -> Self {
+        let mut user = Self::new(value.username);
+        user.set_password(&value.password);
+        user
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginForm {
     pub username: String,
     pub password: String,
+    /// Keeps the session alive for much longer.
+    #[serde(default)]
+    pub remember: bool,
 }
 
 impl LoginForm {
     pub fn verify(&self, data: &UserData) -> Result<(), ServiceError> {
-        let parsed_hash = PasswordHash::new(&data.passhash)?;
+        self.verify_hash(&data.passhash)
+    }
+    pub fn verify_hash(&self, passhash: &str) -> Result<(), ServiceError> {
+        let parsed_hash = PasswordHash::new(passhash)?;
         Ok(Argon2::default().verify_password(self.password.as_bytes(), &parsed_hash)?)
     }
 }
@@ -200,6 +565,20 @@ pub struct AbilityTargets {
     pub name: AbilityName,
     pub targets: HashSet<Coords>,
     pub costs: Vec<(String, i64)>,
+    pub projected_turn: Option<ProjectedTurn>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct TimelineEntry {
+    pub entity_id: uuid::Uuid,
+    pub scenario_player_index: i64,
+    pub time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ProjectedTurn {
+    pub time: i64,
+    pub position: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -210,6 +589,33 @@ pub struct Gamestate {
     pub visible_tiles: HashSet<Coords>,
     pub allied_vision: HashSet<Coords>,
     pub playing: uuid::Uuid,
+    pub timeline: Vec<TimelineEntry>,
+    pub turn_time_left_ms: Option<i64>,
+    pub clocks: HashMap<i64, i64>,
+    pub result: Option<GameResult>,
+    pub spectators: usize,
+    pub teams: HashMap<i64, i64>,
+    pub draw_offers: HashSet<i64>,
+    pub abort_votes: HashSet<i64>,
+}
+
+/// What the game websocket pushes, depending on the phase of the game.
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum ServerMessage {
+    Gamestate(Box<Gamestate>),
+    Draft {
+        draft: DraftState,
+    },
+    /// Hides the board until the next seat has the device.
+    Handoff {
+        handoff: Handoff,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Handoff {
+    pub seat: i64,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -267,6 +673,7 @@ pub struct ScenarioPlayer {
     pub player_points: i64,
     pub drop_tiles: Vec<Coords>,
     pub allowed_clases: Vec<AvailableClass>,
+    pub team: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -332,6 +739,35 @@ pub struct Entity {
     pub next_move_time: i64,
     pub game_class: CharClass,
     pub log: Vec<ActionLog>,
+    #[serde(default)]
+    pub plan: Option<Plan>,
+}
+
+/// Actions queued for an entity, played automatically on its turns.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Plan {
+    pub steps: Vec<PlannedAction>,
+    /// Ennemies in sight when the plan was made, any other showing up cancels it.
+    pub known_ennemies: HashSet<uuid::Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct PlannedAction {
+    pub step: PlanStep,
+    /// Wait instead of cancelling the plan when the step can't be played.
+    #[serde(default)]
+    pub or_wait: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum PlanStep {
+    /// Walks toward the tile over as many turns as needed.
+    MoveTo(Coords),
+    AttackNearest,
+    Use {
+        ability: AbilityName,
+        target: Coords,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -399,6 +835,84 @@ pub struct AvailableClass {
     pub player_points: i64,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProfileQuery {
+    #[serde(default)]
+    pub page: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProfileUpdate {
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasswordChange {
+    pub current: String,
+    pub new: String,
+}
+
+/// The password is asked again before deleting the account.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountDeletion {
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueueRequest {
+    pub scenarios: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewGameRequest {
+    pub scenario_id: i64,
+    #[serde(default)]
+    pub time_control: TimeControl,
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub hot_seat: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub id: uuid::Uuid,
+    pub from: String,
+    pub to: String,
+    pub scenario_id: i64,
+    pub time_control: TimeControl,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChallengeRequest {
+    pub opponent: String,
+    pub scenario_id: i64,
+    #[serde(default)]
+    pub time_control: TimeControl,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InviteQuery {
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Invite {
+    pub code: String,
+    pub link: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpectateQuery {
+    pub seat: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SeatQuery {
+    pub seat: Option<i64>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DeployEntitiesRequest {
     pub scenario_player_id: i64,
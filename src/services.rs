@@ -1,23 +1,47 @@
 use std::{
     collections::{HashMap, HashSet},
     hash::RandomState,
-    time::Duration,
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use password_hash::rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
-use tokio::time::Instant;
-use uuid::Uuid;
+use tokio::sync::mpsc::UnboundedSender;
 
 use crate::{
-    abilities::{Ability, AbilityName, TargetType},
+    abilities::{Ability, AbilityName},
+    accounts::{self, AccountError},
+    admin::{AdminAction, AuditRecord, GameInspection, UserSummary, ADMIN_ROLE},
     charclasses::{self, CharClass},
+    deployment::{self, DeployRejection},
+    draft::{DraftError, DraftState},
+    engine::{is_valid_target, legal_actions, GameAction, RuleError},
+    history::{self, HistoryRecord},
+    matchmaking::{self, Pairing, QueueEntry, QueueStatus},
+    plans::visible_ennemies,
+    profiles::{MatchOutcome, MatchRecord, Profile},
+    scenarios::{self, get_scenario},
     schemas::{
-        AbilityTargets, ActionLog, AvailableClass, Coords, DeployEntitiesRequest, Entity,
-        EntityResponse, Game, GameRef, GameStatus, Gamestate, ScenarioPlayer, TileType,
+        AbilityTargets, AccountDeletion, ActionLog, Challenge, ChallengeRequest, Coords,
+        DeployEntitiesRequest, EndReason, Entity, EntityResponse, Game, GameRef, GameResult,
+        GameStatus, Gamestate, Handoff, Invite, LoginForm, PasswordChange, Plan, PlannedAction,
+        ProfileUpdate, ProjectedTurn, ScenarioPlayer, Seat, ServerMessage, SpectatorSettings,
+        TileType, TimeControl, UserData,
     },
-    stores::{database::Repository, events::Events},
+    stores::{
+        database::Repository, events::Events, login_attempts::LoginAttempts, queue::Queue,
+        sessions::FileSessionStore,
+    },
+    timeline::{self, landing_position, TIMELINE_LENGTH},
+    tokens::{self, ApiToken, CreatedToken, TokenInfo, TokenRequest, TokenScope, MAX_TOKENS},
 };
 
+/// Number of actions after which a game can no longer be aborted.
+pub const ABORT_ACTION_LIMIT: usize = 4;
+/// How long a player must have been away before a teammate can take over their seat.
+pub const TAKEOVER_GRACE: std::time::Duration = std::time::Duration::from_secs(60);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ServiceError {
     StorageError(String),
@@ -25,6 +49,10 @@ pub enum ServiceError {
     QueueError(String),
     NotFound,
     Unauthorized,
+    DeployRejected(DeployRejection),
+    /// Too many failed logins, retry after that many milliseconds.
+    Throttled(i64),
+    Banned,
 }
 
 impl From<password_hash::Error> for ServiceError {
@@ -33,6 +61,12 @@ impl From<password_hash::Error> for ServiceError {
     }
 }
 
+impl From<tower_sessions::session_store::Error> for ServiceError {
+    fn from(value: tower_sessions::session_store::Error) -> Self {
+        Self::StorageError(value.to_string())
+    }
+}
+
 impl From<std::io::Error> for ServiceError {
     fn from(value: std::io::Error) -> Self {
         return Self::StorageError(value.to_string());
@@ -48,8 +82,29 @@ impl From<rmp_serde::decode::Error> for ServiceError {
         return Self::StorageError(value.to_string());
     }
 }
-impl From<std::sync::mpsc::SendError<Gamestate>> for ServiceError {
-    fn from(value: std::sync::mpsc::SendError<Gamestate>) -> Self {
+impl From<RuleError> for ServiceError {
+    fn from(value: RuleError) -> Self {
+        Self::BadRequest(value.to_string())
+    }
+}
+impl From<DraftError> for ServiceError {
+    fn from(value: DraftError) -> Self {
+        Self::BadRequest(value.to_string())
+    }
+}
+impl From<AccountError> for ServiceError {
+    fn from(value: AccountError) -> Self {
+        Self::BadRequest(value.to_string())
+    }
+}
+
+impl From<DeployRejection> for ServiceError {
+    fn from(value: DeployRejection) -> Self {
+        Self::DeployRejected(value)
+    }
+}
+impl From<tokio::sync::mpsc::error::SendError<Gamestate>> for ServiceError {
+    fn from(value: tokio::sync::mpsc::error::SendError<Gamestate>) -> Self {
         return Self::QueueError(value.to_string());
     }
 }
@@ -61,21 +116,84 @@ impl ToString for ServiceError {
             Self::BadRequest(err) | Self::QueueError(err) => err.to_owned(),
             Self::NotFound => "Not found".to_string(),
             Self::Unauthorized => "Unauthorized".to_string(),
+            Self::DeployRejected(rejection) => rejection.to_string(),
+            Self::Throttled(_) => "Too many attempts".to_string(),
+            Self::Banned => "Banned".to_string(),
+        }
+    }
+}
+
+/// Whose eyes a `Gamestate` is computed through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Viewer {
+    Seat(i64),
+    Omniscient,
+}
+
+/// How a user takes part in a game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Membership {
+    Seat(i64),
+    Spectator,
+}
+
+/// Seated players are members of their game, and anyone else is a spectator of
+/// public games allowing them. Others are refused.
+pub async fn membership(
+    repo: &Repository,
+    game_id: &uuid::Uuid,
+    user_id: &str,
+) -> Result<Membership, ServiceError> {
+    let game_list = repo.load_game_list().await?;
+    let game_ref = game_list.get(game_id).ok_or(ServiceError::NotFound)?;
+    let game = repo.load_game(game_id).await?;
+    match user_seat(&game, user_id) {
+        Ok(seat) => Ok(Membership::Seat(seat)),
+        Err(_) if game.spectating.allowed && game_ref.invite_code.is_none() => {
+            Ok(Membership::Spectator)
         }
+        Err(error) => Err(error),
     }
 }
 
 pub fn get_gamestate(game: &Game) -> Result<Gamestate, ServiceError> {
+    let to_play = game.get_trait_entity()?;
+    get_gamestate_for(game, &Viewer::Seat(to_play.scenario_player_index))
+}
+
+pub fn get_gamestate_for(game: &Game, viewer: &Viewer) -> Result<Gamestate, ServiceError> {
     // let ggs_start = Instant::now();
     let to_play = game.get_trait_entity()?;
-    let allied_entities = game.allied_entities(to_play.scenario_player_index);
-    let blocking_entities = game.blocking_entities(to_play.scenario_player_index);
-    let (los_tiles, allied_vision) = get_los_map(
-        &to_play.coords,
-        &allied_entities,
-        &game.map,
-        &blocking_entities,
-    );
+    let (los_tiles, allied_vision) = match viewer {
+        Viewer::Omniscient => (game.map.keys().cloned().collect(), HashSet::new()),
+        Viewer::Seat(index) => {
+            let allied_entities = game.allied_entities(*index);
+            let blocking_entities = game.blocking_entities(*index);
+            let eye = if to_play.scenario_player_index == *index {
+                Some(to_play)
+            } else {
+                allied_entities.iter().min_by_key(|e| e.id).copied()
+            };
+            match eye {
+                None => (HashSet::new(), HashSet::new()),
+                Some(eye) => {
+                    get_los_map(&eye.coords, &allied_entities, &game.map, &blocking_entities)
+                }
+            }
+        }
+    };
+    let is_visible = |e: &Entity| match viewer {
+        Viewer::Omniscient => true,
+        Viewer::Seat(index) => {
+            allied_vision.contains(&e.coords)
+                || los_tiles.contains(&e.coords)
+                || game.team(e.scenario_player_index) == game.team(*index)
+        }
+    };
+    let can_act = match viewer {
+        Viewer::Omniscient => false,
+        Viewer::Seat(index) => to_play.scenario_player_index == *index,
+    };
     let visible_entities: HashMap<Coords, Vec<EntityResponse>, RandomState> = HashMap::from_iter(
         game.entities
             .clone()
@@ -85,11 +203,7 @@ pub fn get_gamestate(game: &Game) -> Result<Gamestate, ServiceError> {
                     coords,
                     vec_e
                         .into_iter()
-                        .filter(|e| {
-                            allied_vision.contains(&e.coords)
-                                || los_tiles.contains(&e.coords)
-                                || e.scenario_player_index == to_play.scenario_player_index
-                        })
+                        .filter(|e| is_visible(e))
                         .map(|e| {
                             EntityResponse::from_entity(
                                 e.clone(),
@@ -103,6 +217,12 @@ pub fn get_gamestate(game: &Game) -> Result<Gamestate, ServiceError> {
             })
             .filter(|(_coords, vec_e)| vec_e.len() != 0),
     );
+    let timeline_entities: Vec<&Entity> = game
+        .entities
+        .values()
+        .flatten()
+        .filter(|e| is_visible(e))
+        .collect();
     let gs = Gamestate {
         id: game.id,
         entities: visible_entities,
@@ -111,36 +231,46 @@ pub fn get_gamestate(game: &Game) -> Result<Gamestate, ServiceError> {
             .game_class
             .get_ability_list()
             .into_iter()
-            .map(|name| AbilityTargets {
-                name: name.clone(),
-                costs: Ability {
+            .filter(|_| can_act)
+            .map(|name| {
+                let ability = Ability {
                     name: name.clone(),
                     caster: to_play.clone(),
-                }
-                .get_costs(),
-                targets: los_tiles
+                };
+                let targets: HashSet<Coords> = los_tiles
                     .clone()
                     .into_iter()
-                    .filter(|tile| {
-                        let default = vec![];
-                        let target_entity = game.entities.get(tile).unwrap_or(&default).get(0);
-                        {
-                            is_valid_target(
-                                &Ability {
-                                    name: name.clone(),
-                                    caster: to_play.clone(),
-                                },
-                                &tile,
-                                target_entity,
-                                &blocking_entities,
-                                &game.map,
-                            )
-                            .is_ok()
-                        }
-                    })
-                    .collect(),
+                    .filter(|tile| is_valid_target(game, &ability, tile).is_ok())
+                    .collect();
+                // Best case for target-dependent delays, e.g. the shortest move
+                let projected_turn = targets
+                    .iter()
+                    .map(|target| to_play.next_move_time + ability.get_delay(target.clone()))
+                    .min()
+                    .and_then(|time| {
+                        landing_position(&timeline_entities, to_play.id, time)
+                            .map(|position| ProjectedTurn { time, position })
+                    });
+                AbilityTargets {
+                    name: name.clone(),
+                    costs: ability.get_costs(),
+                    targets,
+                    projected_turn,
+                }
             })
             .collect(),
+        timeline: timeline::project(&timeline_entities, TIMELINE_LENGTH),
+        turn_time_left_ms: game.turn_time_left(now_ms()),
+        clocks: game.clocks_at(now_ms()),
+        result: game.result.clone(),
+        spectators: 0,
+        teams: game
+            .seats_in_play()
+            .into_iter()
+            .map(|index| (index, game.team(index)))
+            .collect(),
+        draw_offers: game.draw_offers.clone(),
+        abort_votes: game.abort_votes.clone(),
         visible_tiles: los_tiles,
         allied_vision,
     };
@@ -155,71 +285,452 @@ pub fn get_distance(start: &Coords, end: &Coords) -> f64 {
     )
 }
 
+/// Hands the active unit over to a teammate.
 pub async fn transfer_entity(
     repo: Repository,
+    events: Events,
     game_id: uuid::Uuid,
     user_from: String,
     user_to: String,
 ) -> Result<(), ServiceError> {
-    let mut game = repo.load_game(&game_id).await?;
-    let entity = game.get_trait_entity_mut()?;
-    if entity.user_id != user_from {
+    let game = repo.load_game(&game_id).await?;
+    let entity_id = game.get_trait_entity()?.id;
+    delegate_entity(repo, events, game_id, user_from, entity_id, user_to).await
+}
+
+/// Hands control of a unit to a teammate, on behalf of its seat's owner or its
+/// current controller.
+pub async fn delegate_entity(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    entity_id: uuid::Uuid,
+    to: String,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    let entity = game
+        .entities
+        .values()
+        .flatten()
+        .find(|e| e.id == entity_id)
+        .ok_or(ServiceError::NotFound)?;
+    let index = entity.scenario_player_index;
+    if entity.user_id != user_id && game.seat_owner(index) != Some(user_id.as_str()) {
         return Err(ServiceError::Unauthorized);
     }
-    entity.user_id = user_to;
-    repo.save_game(&game).await?;
+    let seats_to = game.seats_of(&to);
+    if seats_to.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Recipient is not seated in this game".to_string(),
+        ));
+    }
+    if seats_to
+        .iter()
+        .all(|seat| game.team(*seat) != game.team(index))
+    {
+        return Err(ServiceError::BadRequest(
+            "Recipient is not a teammate".to_string(),
+        ));
+    }
+    set_controller(&repo, &events, &mut game, |e| e.id == entity_id, &to).await?;
     Ok(())
 }
 
-pub async fn deploy_entities(
+/// Gives a unit back to its seat's owner.
+pub async fn reclaim_entity(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    entity_id: uuid::Uuid,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    let entity = game
+        .entities
+        .values()
+        .flatten()
+        .find(|e| e.id == entity_id)
+        .ok_or(ServiceError::NotFound)?;
+    if game.seat_owner(entity.scenario_player_index) != Some(user_id.as_str()) {
+        return Err(ServiceError::Unauthorized);
+    }
+    set_controller(&repo, &events, &mut game, |e| e.id == entity_id, &user_id).await?;
+    Ok(())
+}
+
+/// Lets a teammate control the units of a seat whose owner has been disconnected for
+/// longer than `TAKEOVER_GRACE`.
+pub async fn take_over_seat(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    index: i64,
+) -> Result<usize, ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    let own_seat = game.seat_of(&user_id).ok_or(ServiceError::Unauthorized)?;
+    let owner = game
+        .seat_owner(index)
+        .ok_or(ServiceError::NotFound)?
+        .to_string();
+    if own_seat == index || game.team(own_seat) != game.team(index) {
+        return Err(ServiceError::Unauthorized);
+    }
+    match events.disconnected_for(game_id, &owner) {
+        None => {
+            return Err(ServiceError::BadRequest(
+                "Player is still connected".to_string(),
+            ))
+        }
+        Some(away) if away < TAKEOVER_GRACE => {
+            return Err(ServiceError::BadRequest(
+                "Player only just disconnected".to_string(),
+            ))
+        }
+        Some(_) => {}
+    }
+    set_controller(
+        &repo,
+        &events,
+        &mut game,
+        |e| e.scenario_player_index == index && e.user_id == owner,
+        &user_id,
+    )
+    .await
+}
+
+/// Takes back every unit of the user's seat.
+pub async fn reclaim_seat(
     repo: Repository,
     events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    index: i64,
+) -> Result<usize, ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    if game.seat_owner(index) != Some(user_id.as_str()) {
+        return Err(ServiceError::Unauthorized);
+    }
+    set_controller(
+        &repo,
+        &events,
+        &mut game,
+        |e| e.scenario_player_index == index,
+        &user_id,
+    )
+    .await
+}
+
+/// Gives the matching units to the user, sending them the turn if the acting unit
+/// is one of them. Returns how many units changed hands.
+async fn set_controller(
+    repo: &Repository,
+    events: &Events,
+    game: &mut Game,
+    matches: impl Fn(&Entity) -> bool,
+    user_id: &str,
+) -> Result<usize, ServiceError> {
+    let mut changed = 0;
+    for entity in game.entities.values_mut().flatten() {
+        if matches(entity) && entity.user_id != user_id {
+            entity.user_id = user_id.to_string();
+            changed += 1;
+        }
+    }
+    repo.save_game(game).await?;
+    if game
+        .get_trait_entity()
+        .is_ok_and(|active| active.user_id == user_id)
+    {
+        let _ = tick(events.clone(), game).await;
+    }
+    Ok(changed)
+}
+
+/// Sets the seat's placements, hidden from the other seats until the game starts.
+pub async fn deploy_entities(
+    repo: Repository,
     user_id: String,
     game_id: uuid::Uuid,
     req: DeployEntitiesRequest,
 ) -> Result<(), ServiceError> {
-    // Check if game is Open
-    // Check if scenario_player_id already deployed
-    // Check points
-    // Check location
+    let _lock = repo.lock_game(&game_id).await;
+    let game_list = repo.load_game_list().await?;
+    let game_ref = game_list.get(&game_id).ok_or(ServiceError::NotFound)?;
     let mut game = repo.load_game(&game_id).await?;
-    let count_indices = HashSet::<i64, RandomState>::from_iter(
-        game.entities
-            .values()
-            .flatten()
-            .map(|e| e.scenario_player_index),
-    )
-    .len();
-
-    for (coords, class) in req.entities {
-        game.entities.insert(
-            coords.clone(),
-            vec![Entity {
-                user_id: user_id.clone(),
-                id: uuid::Uuid::new_v4(),
-                coords: coords,
-                resources: HashMap::from_iter(class.get_resource_list()),
-                scenario_player_index: req.scenario_player_id,
-                last_move_time: 0,
-                next_move_time: 0,
-                game_class: class,
-                log: vec![],
-            }],
-        );
+    if game_ref.status != GameStatus::Open || game.has_started() {
+        return Err(DeployRejection::GameNotOpen.into());
+    }
+    let index = req.scenario_player_id;
+    let player = get_scenario(game_ref.scenario)
+        .and_then(|scenario| scenario.players.into_iter().nth(index as usize))
+        .ok_or(DeployRejection::UnknownSeat)?;
+    let seat = game
+        .seats
+        .get(index as usize)
+        .ok_or(DeployRejection::UnknownSeat)?;
+    if seat.user_id.as_ref() != Some(&user_id) {
+        return Err(DeployRejection::SeatTaken.into());
+    }
+    if !game.all_seats_taken() {
+        return Err(DeployRejection::SeatsNotTaken.into());
+    }
+    match &game.draft {
+        None => deployment::validate(&player, &req.entities)?,
+        Some(draft) if draft.is_over() => deployment::validate_drafted(
+            &player,
+            draft
+                .picks
+                .get(&index)
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+            &req.entities,
+        )?,
+        Some(_) => return Err(DeployRejection::DraftNotOver.into()),
     }
-    if count_indices >= 1 {
-        let mut game_list = repo.load_game_list().await?;
-        game_list.get_mut(&game_id).unwrap().status = GameStatus::Running;
-        repo.save_game_list(game_list).await?;
-        let _res = tick(events, &game).await;
+    let entities: Vec<Entity> = req
+        .entities
+        .into_iter()
+        .map(|(coords, class)| Entity {
+            user_id: user_id.clone(),
+            id: uuid::Uuid::new_v4(),
+            coords,
+            resources: HashMap::from_iter(class.get_resource_list()),
+            scenario_player_index: req.scenario_player_id,
+            last_move_time: 0,
+            next_move_time: 0,
+            game_class: class,
+            log: vec![],
+            plan: None,
+        })
+        .collect();
+    game.deployments.insert(index, entities);
+    game.update_phase(now_ms());
+    repo.save_game(&game).await?;
+    update_seats(&repo, &game).await
+}
+
+/// The seat's placements, as long as they are still hidden.
+pub async fn get_deployment(
+    repo: Repository,
+    game_id: uuid::Uuid,
+    user_id: String,
+    seat: Option<i64>,
+) -> Result<Vec<Entity>, ServiceError> {
+    let game = repo.load_game(&game_id).await?;
+    let index = match seat {
+        Some(index) if game.seat_owner(index) == Some(user_id.as_str()) => index,
+        Some(_) => return Err(ServiceError::Unauthorized),
+        None => game.seat_of(&user_id).ok_or(ServiceError::Unauthorized)?,
     };
+    game.deployments
+        .get(&index)
+        .cloned()
+        .ok_or(ServiceError::NotFound)
+}
+
+/// Reveals every placement together and sends the first tick. Seats that didn't
+/// deploy in time sit out, and the game is aborted when less than two teams are left.
+pub async fn start_game(
+    repo: &Repository,
+    events: &Events,
+    game: &mut Game,
+) -> Result<(), ServiceError> {
+    for entities in game.reveal_deployments() {
+        repo.append_history(&game.id, &HistoryRecord::Deployed { entities })
+            .await?;
+    }
+    let teams: HashSet<i64> = game
+        .seats_in_play()
+        .into_iter()
+        .map(|index| game.team(index))
+        .collect();
+    if teams.len() < 2 {
+        let result = GameResult {
+            winners: vec![],
+            reason: EndReason::Aborted,
+        };
+        return finish_game(repo, events, game, result).await;
+    }
+    game.turn_started_at = now_ms();
+    repo.save_game(game).await?;
+    repo.update_game_list(|game_list| {
+        if let Some(game_ref) = game_list.get_mut(&game.id) {
+            game_ref.status = GameStatus::Running;
+            game_ref.deployment_deadline = None;
+        }
+        Ok(())
+    })
+    .await?;
+    broadcast(events, game).await;
+    notify_spectators(repo, events, game).await;
+    Ok(())
+}
+
+/// Plays draft turns out of time, and starts the game once the deployment timer is over.
+pub async fn check_deployment(
+    repo: &Repository,
+    events: &Events,
+    game_id: uuid::Uuid,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = repo.load_game(&game_id).await?;
+    if let Some(draft) = &mut game.draft {
+        let now = now_ms();
+        if draft.time_left(now) == Some(0) {
+            draft.timeout(now);
+            game.update_phase(now);
+            repo.save_game(&game).await?;
+            update_seats(repo, &game).await?;
+            push_draft(events, &game).await;
+        }
+    }
+    match game.deployment_deadline {
+        Some(deadline) if deadline <= now_ms() && !game.has_started() => {
+            start_game(repo, events, &mut game).await
+        }
+        _ => Ok(()),
+    }
+}
+
+pub async fn get_draft(repo: Repository, game_id: uuid::Uuid) -> Result<DraftState, ServiceError> {
+    repo.load_game(&game_id)
+        .await?
+        .draft
+        .ok_or(ServiceError::NotFound)
+}
+
+/// Bans or picks `class` for the user's seat, on its turn of the draft.
+pub async fn draft_class(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    class: CharClass,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_lobby(&repo, &game_id).await?;
+    let index = user_seat(&game, &user_id)?;
+    let now = now_ms();
+    game.draft
+        .as_mut()
+        .ok_or(ServiceError::BadRequest("Game has no draft".to_string()))?
+        .choose(index, class, now)?;
+    game.update_phase(now);
     repo.save_game(&game).await?;
+    update_seats(&repo, &game).await?;
+    push_draft(&events, &game).await;
     Ok(())
 }
 
+/// Sends every seat the state of the draft.
+pub async fn push_draft(events: &Events, game: &Game) {
+    let Some(draft) = &game.draft else {
+        return;
+    };
+    for user_id in game.seats.iter().filter_map(|seat| seat.user_id.clone()) {
+        let message = ServerMessage::Draft {
+            draft: draft.clone(),
+        };
+        let _res = events.send_message(message, game.id, user_id).await;
+    }
+}
+
+/// Lets the next player know it's their turn, and spectators what happened.
+pub async fn publish(repo: &Repository, events: &Events, game: &Game) {
+    let _res = tick(events.clone(), game).await;
+    notify_spectators(repo, events, game).await;
+}
+
+/// The game as spectators may see it, some actions behind while it is running.
+pub async fn spectated_game(repo: &Repository, game: &Game) -> Result<Game, ServiceError> {
+    if game.result.is_some() || game.spectating.delay == 0 {
+        return Ok(game.clone());
+    }
+    let records = repo.load_history(&game.id).await?;
+    history::replay(game.id, history::delayed(&records, game.spectating.delay))?
+        .pop()
+        .ok_or(ServiceError::NotFound)
+}
+
+pub async fn notify_spectators(repo: &Repository, events: &Events, game: &Game) {
+    if events.spectator_count(game.id) == 0 {
+        return;
+    }
+    match spectated_game(repo, game).await {
+        Ok(spectated) => {
+            events
+                .send_spectators(game.id, |viewer| get_gamestate_for(&spectated, viewer).ok())
+                .await
+        }
+        Err(error) => tracing::info!("spectators error: {}", error.to_string()),
+    }
+}
+
+pub async fn spectate(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    viewer: Viewer,
+    sender: UnboundedSender<Gamestate>,
+) -> Result<uuid::Uuid, ServiceError> {
+    let game = repo.load_game(&game_id).await?;
+    if !game.spectating.allowed {
+        return Err(ServiceError::Unauthorized);
+    }
+    if !game.seats_of(&user_id).is_empty()
+        || game
+            .entities
+            .values()
+            .flatten()
+            .any(|e| e.user_id == user_id)
+    {
+        return Err(ServiceError::BadRequest(
+            "Players can't spectate their own game".to_string(),
+        ));
+    }
+    if let Ok(gamestate) = get_gamestate_for(&spectated_game(&repo, &game).await?, &viewer) {
+        sender.send(gamestate)?;
+    }
+    Ok(events.register_spectator(game_id, viewer, sender).await)
+}
+
+pub async fn set_spectator_settings(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    settings: SpectatorSettings,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = repo.load_game(&game_id).await?;
+    if game.creator != user_id {
+        return Err(ServiceError::Unauthorized);
+    }
+    if !settings.allowed {
+        events.remove_spectators(game_id);
+    }
+    game.spectating = settings;
+    repo.save_game(&game).await
+}
+
 pub async fn tick(events: Events, game: &Game) -> Result<(), ServiceError> {
     let to_play = game.get_trait_entity()?;
     tracing::info!("sending tick to {}", to_play.user_id);
+    if let Some(seat) = game.awaiting_handoff() {
+        let handoff = ServerMessage::Handoff {
+            handoff: Handoff { seat },
+        };
+        return events
+            .send_message(handoff, game.id, to_play.user_id.clone())
+            .await;
+    }
     events
         .send_event(get_gamestate(game)?, game.id, to_play.user_id.clone())
         .await
@@ -397,220 +908,1516 @@ pub fn get_los_map(
     )
 }
 
-pub async fn get_active_game(repo: Repository, user_id: String) -> Result<Vec<Uuid>, ServiceError> {
+/// Public open games, with their seats, along with the ones the user created or is
+/// seated in.
+pub async fn get_active_game(
+    repo: Repository,
+    user_id: String,
+) -> Result<Vec<GameRef>, ServiceError> {
     let game_list = repo.load_game_list().await?;
     Ok(game_list
-        .keys()
-        .filter(|k| {
-            let game_ref = game_list.get(k).unwrap();
-            game_ref.status == GameStatus::Open || game_ref.seated_players.contains(&user_id)
+        .into_values()
+        .filter(|game_ref| {
+            let open = game_ref.status == GameStatus::Open;
+            open && (game_ref.invite_code.is_none() || game_ref.creator == user_id)
+                || game_ref.seated_players.contains(&user_id)
         })
-        .map(|k| k.clone())
         .collect())
 }
 
 pub fn get_scenarios() -> Vec<i64> {
-    vec![0]
+    scenarios::get_scenario_ids()
 }
 
-pub fn get_scenario_players() -> Vec<ScenarioPlayer> {
-    let allowed_classes = vec![
-        AvailableClass {
-            player_points: 25,
-            game_class: CharClass::Archer,
-        },
-        AvailableClass {
-            player_points: 25,
-            game_class: CharClass::Warrior,
-        },
-    ];
-    vec![
-        ScenarioPlayer {
-            player_points: 100,
-            drop_tiles: vec![Coords { x: -2, y: 0 }],
-            allowed_clases: allowed_classes.clone(),
-        },
-        ScenarioPlayer {
-            player_points: 100,
-            drop_tiles: vec![Coords { x: 2, y: 0 }],
-            allowed_clases: allowed_classes,
-        },
-    ]
+pub fn get_scenario_players(scenario_id: i64) -> Result<Vec<ScenarioPlayer>, ServiceError> {
+    Ok(get_scenario(scenario_id)
+        .ok_or(ServiceError::NotFound)?
+        .players)
 }
 
 pub async fn get_available_scenario_players(
     repo: Repository,
     game_id: uuid::Uuid,
 ) -> Result<Vec<ScenarioPlayer>, ServiceError> {
-    let allowed_classes = vec![
-        AvailableClass {
-            player_points: 25,
-            game_class: CharClass::Archer,
-        },
-        AvailableClass {
-            player_points: 25,
-            game_class: CharClass::Warrior,
-        },
-    ];
-    Ok(vec![
-        ScenarioPlayer {
-            player_points: 100,
-            drop_tiles: vec![Coords { x: -2, y: 0 }],
-            allowed_clases: allowed_classes.clone(),
-        },
-        ScenarioPlayer {
-            player_points: 100,
-            drop_tiles: vec![Coords { x: 2, y: 0 }],
-            allowed_clases: allowed_classes,
-        },
-    ])
+    let game_list = repo.load_game_list().await?;
+    let game_ref = game_list.get(&game_id).ok_or(ServiceError::NotFound)?;
+    get_scenario_players(game_ref.scenario)
 }
 
-pub async fn new_game(repo: Repository) -> Result<uuid::Uuid, ServiceError> {
-    let game = Game::new();
+pub async fn new_game(
+    repo: Repository,
+    user_id: String,
+    scenario_id: i64,
+    time_control: TimeControl,
+    private: bool,
+    hot_seat: bool,
+) -> Result<uuid::Uuid, ServiceError> {
+    let scenario = get_scenario(scenario_id)
+        .ok_or(ServiceError::BadRequest("No such scenario".to_string()))?;
+    let mut game = Game::from_scenario(&scenario);
+    game.creator = user_id.clone();
+    game.time_control = time_control;
+    game.hot_seat = hot_seat;
+    repo.append_history(
+        &game.id,
+        &HistoryRecord::Created {
+            seed: game.seed,
+            map: game.map.clone(),
+            seats: game.seats.clone(),
+            friendly_fire: game.friendly_fire,
+        },
+    )
+    .await?;
     repo.save_game(&game).await?;
-    let mut game_list = match repo.load_game_list().await {
-        Err(_) => HashMap::new(),
-        Ok(list) => list,
+    let game_ref = GameRef {
+        game_id: game.id,
+        seated_players: vec![],
+        status: GameStatus::Open,
+        scenario: scenario_id,
+        result: None,
+        seats: game.seats.clone(),
+        deployment_deadline: None,
+        drafting: false,
+        creator: user_id,
+        invite_code: private.then(generate_invite_code),
+        hot_seat,
     };
-    game_list.insert(
-        game.id,
-        GameRef {
-            game_id: game.id,
-            seated_players: vec![],
-            status: GameStatus::Open,
-            scenario: 0,
-        },
-    );
-    repo.save_game_list(game_list).await?;
+    repo.update_game_list(|game_list| {
+        game_list.insert(game.id, game_ref);
+        Ok(())
+    })
+    .await?;
     Ok(game.id)
 }
 
-pub fn is_valid_target(
-    ability: &Ability,
-    target: &Coords,
-    target_entity: Option<&Entity>,
-    blocking_entities: &Vec<&Entity>,
-    map: &HashMap<Coords, TileType>,
+fn generate_invite_code() -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    (0..8)
+        .map(|_| ALPHABET[OsRng.next_u32() as usize % ALPHABET.len()] as char)
+        .collect()
+}
+
+/// Private games can only be joined with their invite code, and look like they
+/// don't exist otherwise.
+async fn check_invite(
+    repo: &Repository,
+    game_id: &uuid::Uuid,
+    user_id: &str,
+    code: Option<&str>,
 ) -> Result<(), ServiceError> {
-    let distance = get_distance(&ability.caster.coords, target);
-    if distance > ability.max_range(&ability.caster.game_class) {
-        return Err(ServiceError::BadRequest("Out of range".to_string()));
-    }
-    match ability.target_type() {
-        TargetType::Walkable => {
-            if target_entity.is_some() {
-                return Err(ServiceError::BadRequest(
-                    "Target must be walkable".to_string(),
-                ));
-            }
-            match map.get(target) {
-                Some(TileType::Wall) | Some(TileType::DeepWater) => {
-                    return Err(ServiceError::BadRequest(
-                        "Can't walk on that tile".to_string(),
-                    ))
-                }
-                _ => {}
-            }
-        }
-        TargetType::Ennemy => {
-            if target_entity.is_none()
-                || target_entity.clone().is_some_and(|e| {
-                    e.scenario_player_index == ability.caster.scenario_player_index
-                })
-            {
-                return Err(ServiceError::BadRequest(
-                    "Target must be an ennemy".to_string(),
-                ));
-            }
-        }
-        TargetType::Ally => {
-            if target_entity.is_none()
-                || target_entity.clone().is_some_and(|e| {
-                    e.scenario_player_index != ability.caster.scenario_player_index
-                })
-            {
-                return Err(ServiceError::BadRequest(
-                    "Target must be an ally".to_string(),
-                ));
-            }
-        }
-        TargetType::Selfcast => {
-            if target_entity != Some(&ability.caster) {
-                return Err(ServiceError::BadRequest(
-                    "This ability is self-cast".to_string(),
-                ));
-            }
-        }
-    }
-    if ability.needs_los() {
-        if has_los(
-            &ability.caster.coords,
-            target,
-            map,
-            blocking_entities,
-            TileType::is_blocking_sight,
-        )
-        .is_err()
-        {
-            return Err(ServiceError::BadRequest(
-                "Target is out of line of sight".to_string(),
-            ));
+    let game_list = repo.load_game_list().await?;
+    let game_ref = game_list.get(game_id).ok_or(ServiceError::NotFound)?;
+    match &game_ref.invite_code {
+        Some(invite_code) if game_ref.creator != user_id && code != Some(invite_code) => {
+            Err(ServiceError::NotFound)
         }
+        _ => Ok(()),
     }
-    Ok(())
 }
 
-pub async fn use_ability(
+/// Invite code and link of a private game, for its creator to share.
+pub async fn get_invite(
     repo: Repository,
-    events: Events,
     game_id: uuid::Uuid,
     user_id: String,
-    ability_name: AbilityName,
-    target: Coords,
-) -> Result<(), ServiceError> {
-    let start = Instant::now();
-    let game = repo.load_game(&game_id).await?;
-    let entity = game.get_trait_entity()?;
-    let current_time = entity.next_move_time;
-    if entity.user_id != user_id {
+) -> Result<Invite, ServiceError> {
+    let game_list = repo.load_game_list().await?;
+    let game_ref = game_list.get(&game_id).ok_or(ServiceError::NotFound)?;
+    if game_ref.creator != user_id {
         return Err(ServiceError::Unauthorized);
     }
-    let target_entity = game.entities.get(&target).map(|v| v.get(0)).unwrap_or(None);
-    let ability = Ability {
-        name: ability_name.clone(),
-        caster: entity.clone(),
-    };
+    let code = game_ref.invite_code.clone().ok_or(ServiceError::NotFound)?;
+    Ok(Invite {
+        link: format!("/invite/{}", code),
+        code,
+    })
+}
 
-    let blocking_entities = game.blocking_entities(entity.scenario_player_index);
-    is_valid_target(
-        &ability,
-        &target,
-        target_entity,
-        &blocking_entities,
-        &game.map,
-    )?;
-    let costs = ability.get_costs();
-    for (resource_name, cost) in costs.clone() {
-        let resource = entity.resources.get(&resource_name);
-        if resource.is_none() || resource.unwrap().current < cost {
-            return Err(ServiceError::BadRequest(format!(
-                "Ability is not ready, lack resource {}",
-                resource_name
-            )));
-        }
-    }
-    let mut mut_game = game.clone();
-    mut_game.apply_ability(&ability, &target);
+/// Seats the user in the private game the code belongs to.
+pub async fn join_by_invite(
+    repo: Repository,
+    events: Events,
+    user_id: String,
+    code: String,
+) -> Result<uuid::Uuid, ServiceError> {
+    let game_list = repo.load_game_list().await?;
+    let game_id = game_list
+        .values()
+        .find(|game_ref| game_ref.invite_code.as_deref() == Some(code.as_str()))
+        .map(|game_ref| game_ref.game_id)
+        .ok_or(ServiceError::NotFound)?;
+    join_game(repo, events, game_id, user_id, Some(code)).await?;
+    Ok(game_id)
+}
 
-    let new_entity = mut_game.get_trait_entity()?;
-    let elapsed_time = new_entity.next_move_time - current_time;
+/// Mirrors the seats of the game in the game list.
+async fn update_seats(repo: &Repository, game: &Game) -> Result<(), ServiceError> {
+    repo.update_game_list(|game_list| {
+        let game_ref = game_list.get_mut(&game.id).ok_or(ServiceError::NotFound)?;
+        game_ref.seats = game.seats.clone();
+        game_ref.deployment_deadline = game.deployment_deadline;
+        game_ref.drafting = game
+            .draft
+            .as_ref()
+            .is_some_and(|draft| draft.current().is_some());
+        game_ref.seated_players = game
+            .seats
+            .iter()
+            .filter_map(|seat| seat.user_id.clone())
+            .collect();
+        Ok(())
+    })
+    .await
+}
 
-    mut_game.increment_resources(elapsed_time);
-    let gamestate = get_gamestate(&mut_game)?;
-    let _res = events.send_event(gamestate, game_id, user_id).await;
+async fn load_lobby(repo: &Repository, game_id: &uuid::Uuid) -> Result<Game, ServiceError> {
+    let game = repo.load_game(game_id).await?;
+    if game.has_started() {
+        return Err(ServiceError::BadRequest(
+            "Game has already started".to_string(),
+        ));
+    }
+    Ok(game)
+}
 
-    repo.save_game(&mut_game).await?;
+/// Seats the user at the first free seat of the game.
+pub async fn join_game(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    code: Option<String>,
+) -> Result<i64, ServiceError> {
+    check_invite(&repo, &game_id, &user_id, code.as_deref()).await?;
+    let game = load_lobby(&repo, &game_id).await?;
+    let index = game
+        .seats
+        .iter()
+        .position(|seat| seat.user_id.is_none())
+        .ok_or(ServiceError::BadRequest("Game is full".to_string()))?;
+    claim_seat(repo, events, game_id, user_id, index as i64, code).await?;
+    Ok(index as i64)
+}
 
-    Ok(())
+pub async fn claim_seat(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    index: i64,
+    code: Option<String>,
+) -> Result<i64, ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    check_invite(&repo, &game_id, &user_id, code.as_deref()).await?;
+    let mut game = load_lobby(&repo, &game_id).await?;
+    if game.seat_of(&user_id).is_some() && !game.hot_seat {
+        return Err(ServiceError::BadRequest("Already seated".to_string()));
+    }
+    let seat = usize::try_from(index)
+        .ok()
+        .and_then(|i| game.seats.get_mut(i))
+        .ok_or(ServiceError::NotFound)?;
+    if seat.user_id.is_some() {
+        return Err(ServiceError::BadRequest("Seat is taken".to_string()));
+    }
+    seat.user_id = Some(user_id);
+    seat.ready = false;
+    let draft = game.draft.clone();
+    game.update_phase(now_ms());
+    repo.save_game(&game).await?;
+    update_seats(&repo, &game).await?;
+    if game.draft != draft {
+        push_draft(&events, &game).await;
+    }
+    Ok(index)
+}
+
+pub async fn leave_seat(
+    repo: Repository,
+    game_id: uuid::Uuid,
+    user_id: String,
+    index: i64,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_lobby(&repo, &game_id).await?;
+    if game.seat_owner(index) != Some(user_id.as_str()) {
+        return Err(ServiceError::Unauthorized);
+    }
+    if game
+        .draft
+        .as_ref()
+        .is_some_and(|draft| draft.turn_started_at.is_some())
+    {
+        return Err(ServiceError::BadRequest("Draft has started".to_string()));
+    }
+    game.seats[index as usize] = Seat {
+        user_id: None,
+        ready: false,
+        ..game.seats[index as usize].clone()
+    };
+    game.deployments.remove(&index);
+    game.update_phase(now_ms());
+    repo.save_game(&game).await?;
+    update_seats(&repo, &game).await
+}
+
+/// Commits the seat's placements, the game starting once every seat has.
+pub async fn set_ready(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    index: i64,
+    ready: bool,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_lobby(&repo, &game_id).await?;
+    if game.seat_owner(index) != Some(user_id.as_str()) {
+        return Err(ServiceError::Unauthorized);
+    }
+    if ready && !game.deployments.contains_key(&index) {
+        return Err(ServiceError::BadRequest(
+            "Deploy before getting ready".to_string(),
+        ));
+    }
+    game.seats[index as usize].ready = ready;
+    repo.save_game(&game).await?;
+    update_seats(&repo, &game).await?;
+    if game.all_seats_ready() {
+        start_game(&repo, &events, &mut game).await?;
+    }
+    Ok(())
+}
+
+pub async fn use_ability(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+    ability_name: AbilityName,
+    target: Coords,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = repo.load_game(&game_id).await?;
+    let seat = user_seat(&game, &user_id)?;
+    let entity = game.get_trait_entity()?;
+    if entity.user_id != user_id || game.team(entity.scenario_player_index) != game.team(seat) {
+        return Err(ServiceError::Unauthorized);
+    }
+    if game.awaiting_handoff().is_some() {
+        return Err(ServiceError::BadRequest(
+            "Pass the device to the next seat first".to_string(),
+        ));
+    }
+    let action = GameAction {
+        entity_id: entity.id,
+        ability: ability_name,
+        target,
+    };
+    let index = entity.scenario_player_index;
+    game.timeouts.remove(&index);
+    play_action(&repo, &events, &mut game, action).await
+}
+
+/// Confirms the next seat of a hot-seat game has the device, and shows it its turn.
+pub async fn confirm_handoff(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    let seat = game
+        .awaiting_handoff()
+        .ok_or(ServiceError::BadRequest("No handoff pending".to_string()))?;
+    if game.get_trait_entity()?.user_id != user_id {
+        return Err(ServiceError::Unauthorized);
+    }
+    game.shown_seat = Some(seat);
+    repo.save_game(&game).await?;
+    tick(events, &game).await
+}
+
+/// Applies `action`, records it and lets the next entity know it's their turn.
+pub async fn play_action(
+    repo: &Repository,
+    events: &Events,
+    game: &mut Game,
+    action: GameAction,
+) -> Result<(), ServiceError> {
+    if game.result.is_some() {
+        return Err(ServiceError::BadRequest("Game is over".to_string()));
+    }
+    let now = now_ms();
+    let mut played = game.clone();
+    if !played.charge_clock(now) {
+        let index = game.get_trait_entity()?.scenario_player_index;
+        eliminate_seat(repo, events, game, index, EndReason::OutOfTime).await?;
+        return Err(ServiceError::BadRequest("Out of time".to_string()));
+    }
+    played.apply(&action)?;
+    *game = played;
+    // Draw offers lapse once the game moves on.
+    game.draw_offers.clear();
+    repo.append_history(&game.id, &HistoryRecord::Action(action))
+        .await?;
+    run_plans(repo, game).await?;
+    if let Some(result) = game.result.clone() {
+        return finish_game(repo, events, game, result).await;
+    }
+    game.turn_started_at = now;
+    repo.save_game(game).await?;
+    publish(repo, events, game).await;
+    Ok(())
+}
+
+/// Plays queued actions for as long as the active entity has a plan.
+async fn run_plans(repo: &Repository, game: &mut Game) -> Result<(), ServiceError> {
+    while game.result.is_none() {
+        let Some(action) = game.take_planned_action() else {
+            break;
+        };
+        game.apply(&action)?;
+        repo.append_history(&game.id, &HistoryRecord::Action(action))
+            .await?;
+    }
+    Ok(())
+}
+
+fn owned_entity<'a>(
+    game: &'a Game,
+    entity_id: &uuid::Uuid,
+    user_id: &str,
+) -> Result<&'a Entity, ServiceError> {
+    let entity = game
+        .entities
+        .values()
+        .flatten()
+        .find(|e| &e.id == entity_id)
+        .ok_or(ServiceError::NotFound)?;
+    if entity.user_id != user_id {
+        return Err(ServiceError::Unauthorized);
+    }
+    Ok(entity)
+}
+
+pub async fn get_plan(
+    repo: Repository,
+    game_id: uuid::Uuid,
+    entity_id: uuid::Uuid,
+    user_id: String,
+) -> Result<Vec<PlannedAction>, ServiceError> {
+    let game = repo.load_game(&game_id).await?;
+    let entity = owned_entity(&game, &entity_id, &user_id)?;
+    Ok(entity.plan.clone().map(|p| p.steps).unwrap_or_default())
+}
+
+/// Replaces the plan of an entity, an empty list of steps cancelling it.
+pub async fn set_plan(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    entity_id: uuid::Uuid,
+    user_id: String,
+    steps: Vec<PlannedAction>,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = repo.load_game(&game_id).await?;
+    if game.result.is_some() {
+        return Err(ServiceError::BadRequest("Game is over".to_string()));
+    }
+    let entity = owned_entity(&game, &entity_id, &user_id)?;
+    let plan = if steps.is_empty() {
+        None
+    } else {
+        Some(Plan {
+            known_ennemies: visible_ennemies(&game, entity),
+            steps,
+        })
+    };
+    let is_active = game.get_trait_entity()?.id == entity_id;
+    if let Some(entity) = game
+        .entities
+        .values_mut()
+        .flatten()
+        .find(|e| e.id == entity_id)
+    {
+        entity.plan = plan;
+    }
+    if is_active {
+        run_plans(&repo, &mut game).await?;
+        if let Some(result) = game.result.clone() {
+            return finish_game(&repo, &events, &mut game, result).await;
+        }
+        game.turn_started_at = now_ms();
+        repo.save_game(&game).await?;
+        publish(&repo, &events, &game).await;
+        return Ok(());
+    }
+    repo.save_game(&game).await
+}
+
+/// Takes the seat out of the game, which ends once a single team is left.
+pub async fn eliminate_seat(
+    repo: &Repository,
+    events: &Events,
+    game: &mut Game,
+    index: i64,
+    reason: EndReason,
+) -> Result<(), ServiceError> {
+    let record = match reason {
+        EndReason::Resignation => HistoryRecord::Resigned { seat: index },
+        _ => HistoryRecord::Eliminated { seat: index },
+    };
+    repo.append_history(&game.id, &record).await?;
+    let eliminated: HashSet<String> = game
+        .entities
+        .values()
+        .flatten()
+        .filter(|e| e.scenario_player_index == index)
+        .map(|e| e.user_id.clone())
+        .collect();
+    match game.forfeit(index, reason) {
+        Some(result) => finish_game(repo, events, game, result).await?,
+        None => {
+            game.turn_started_at = now_ms();
+            repo.save_game(game).await?;
+            publish(repo, events, game).await;
+        }
+    }
+    // Their entities are gone, so `broadcast` no longer reaches them
+    for user_id in eliminated {
+        if game.shares_device(&user_id) {
+            continue;
+        }
+        if let Ok(gamestate) = get_gamestate_for(game, &Viewer::Seat(index)) {
+            let _res = events.send_event(gamestate, game.id, user_id).await;
+        }
+    }
+    Ok(())
+}
+
+/// Seat the user plays in the game. In hot-seat games, the one whose turn it is to
+/// draft or play among the user's seats.
+fn user_seat(game: &Game, user_id: &str) -> Result<i64, ServiceError> {
+    let seats = game.seats_of(user_id);
+    let active = match &game.draft {
+        Some(draft) if !draft.is_over() => draft.current().map(|turn| turn.seat),
+        _ => game
+            .get_trait_entity()
+            .ok()
+            .map(|entity| entity.scenario_player_index),
+    };
+    active
+        .filter(|seat| seats.contains(seat))
+        .or(seats.first().copied())
+        .or_else(|| {
+            game.entities
+                .values()
+                .flatten()
+                .find(|e| e.user_id == user_id)
+                .map(|e| e.scenario_player_index)
+        })
+        .ok_or(ServiceError::Unauthorized)
+}
+
+async fn load_unfinished_game(
+    repo: &Repository,
+    game_id: &uuid::Uuid,
+) -> Result<Game, ServiceError> {
+    let game = repo.load_game(game_id).await?;
+    match game.result {
+        Some(_) => Err(ServiceError::BadRequest("Game is over".to_string())),
+        None => Ok(game),
+    }
+}
+
+/// Records and applies a seat's say in how the game ends, finishing it once everyone agrees.
+async fn record_agreement(
+    repo: &Repository,
+    events: &Events,
+    game: &mut Game,
+    record: HistoryRecord,
+) -> Result<(), ServiceError> {
+    repo.append_history(&game.id, &record).await?;
+    game.replay_record(&record)?;
+    if let Some(result) = game.result.clone() {
+        return finish_game(repo, events, game, result).await;
+    }
+    repo.save_game(game).await?;
+    broadcast(events, game).await;
+    notify_spectators(repo, events, game).await;
+    Ok(())
+}
+
+pub async fn resign(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    if !game.has_started() {
+        return Err(ServiceError::BadRequest(
+            "Game hasn't started, leave the seat instead".to_string(),
+        ));
+    }
+    let index = user_seat(&game, &user_id)?;
+    eliminate_seat(&repo, &events, &mut game, index, EndReason::Resignation).await
+}
+
+pub async fn offer_draw(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    let seat = user_seat(&game, &user_id)?;
+    record_agreement(
+        &repo,
+        &events,
+        &mut game,
+        HistoryRecord::DrawOffered { seat },
+    )
+    .await
+}
+
+pub async fn accept_draw(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    let seat = user_seat(&game, &user_id)?;
+    if game
+        .draw_offers
+        .iter()
+        .all(|index| game.team(*index) == game.team(seat))
+    {
+        return Err(ServiceError::BadRequest("No draw offered".to_string()));
+    }
+    record_agreement(
+        &repo,
+        &events,
+        &mut game,
+        HistoryRecord::DrawOffered { seat },
+    )
+    .await
+}
+
+pub async fn decline_draw(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    let seat = user_seat(&game, &user_id)?;
+    if game.draw_offers.is_empty() {
+        return Err(ServiceError::BadRequest("No draw offered".to_string()));
+    }
+    record_agreement(
+        &repo,
+        &events,
+        &mut game,
+        HistoryRecord::DrawDeclined { seat },
+    )
+    .await
+}
+
+pub async fn abort_game(
+    repo: Repository,
+    events: Events,
+    game_id: uuid::Uuid,
+    user_id: String,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = load_unfinished_game(&repo, &game_id).await?;
+    let seat = user_seat(&game, &user_id)?;
+    let actions = repo
+        .load_history(&game_id)
+        .await?
+        .iter()
+        .filter(|record| matches!(record, HistoryRecord::Action(_)))
+        .count();
+    if actions >= ABORT_ACTION_LIMIT {
+        return Err(ServiceError::BadRequest(
+            "Too late to abort the game".to_string(),
+        ));
+    }
+    record_agreement(
+        &repo,
+        &events,
+        &mut game,
+        HistoryRecord::AbortVoted { seat },
+    )
+    .await
+}
+
+pub async fn finish_game(
+    repo: &Repository,
+    events: &Events,
+    game: &mut Game,
+    result: GameResult,
+) -> Result<(), ServiceError> {
+    game.result = Some(result.clone());
+    repo.append_history(&game.id, &HistoryRecord::Ended(result.clone()))
+        .await?;
+    repo.save_game(game).await?;
+    repo.update_game_list(|game_list| {
+        if let Some(game_ref) = game_list.get_mut(&game.id) {
+            game_ref.status = GameStatus::Finished;
+            game_ref.result = Some(result.clone());
+        }
+        Ok(())
+    })
+    .await?;
+    broadcast(events, game).await;
+    notify_spectators(repo, events, game).await;
+    if let Err(error) = record_results(repo, game, &result).await {
+        tracing::info!("results error: {}", error.to_string());
+    }
+    Ok(())
+}
+
+/// Updates the profile of every player of the game, and their ratings if it was rated.
+async fn record_results(
+    repo: &Repository,
+    game: &Game,
+    result: &GameResult,
+) -> Result<(), ServiceError> {
+    let mut players: Vec<(i64, String, Vec<CharClass>)> = vec![];
+    for record in repo.load_history(&game.id).await? {
+        let HistoryRecord::Deployed { entities } = record else {
+            continue;
+        };
+        for entity in entities {
+            let index = entity.scenario_player_index;
+            match players.iter_mut().find(|(i, _, _)| *i == index) {
+                Some((_, _, classes)) => classes.push(entity.game_class),
+                None => players.push((index, entity.user_id, vec![entity.game_class])),
+            }
+        }
+    }
+    players.sort_by_key(|(index, _, _)| *index);
+    // Users playing several seats of a hot-seat game have no single outcome.
+    let mut seat_counts: HashMap<String, usize> = HashMap::new();
+    for (_, user_id, _) in &players {
+        *seat_counts.entry(user_id.clone()).or_default() += 1;
+    }
+    players.retain(|(_, user_id, _)| seat_counts[user_id] == 1);
+    let mut users = vec![];
+    for (_, user_id, _) in &players {
+        users.push(repo.load_user(user_id).await?);
+    }
+    let ratings: Vec<(i64, i64, i64)> = players
+        .iter()
+        .zip(&users)
+        .map(|((index, _, _), user)| (*index, game.team(*index), user.rating.elo))
+        .collect();
+    let new_ratings = matchmaking::updated_ratings(&ratings, result);
+    let game_list = repo.load_game_list().await?;
+    let scenario = game_list
+        .get(&game.id)
+        .map_or(0, |game_ref| game_ref.scenario);
+    for (((index, _, classes), mut user), elo) in players.into_iter().zip(users).zip(new_ratings) {
+        let outcome = MatchOutcome::of_seat(result, index);
+        user.stats.record(outcome, &classes);
+        user.last_seen_at = now_ms();
+        if game.rated && outcome != MatchOutcome::Aborted {
+            user.rating.elo = elo;
+            user.rating.games += 1;
+        }
+        user.matches.push(MatchRecord {
+            game_id: game.id,
+            scenario,
+            finished_at: now_ms(),
+            outcome,
+            rating: game.rated.then_some(user.rating.elo),
+        });
+        repo.save_user(&user).await?;
+    }
+    Ok(())
+}
+
+pub async fn get_profile(
+    repo: Repository,
+    user_id: String,
+    page: usize,
+) -> Result<Profile, ServiceError> {
+    let user = repo.load_user(&user_id).await?;
+    Ok(Profile::from_user(&user, page))
+}
+
+pub async fn update_profile(
+    repo: Repository,
+    user_id: String,
+    profile_id: String,
+    update: ProfileUpdate,
+) -> Result<Profile, ServiceError> {
+    if user_id != profile_id {
+        return Err(ServiceError::Unauthorized);
+    }
+    let mut user = repo.load_user(&user_id).await?;
+    user.display_name = accounts::normalize_display_name(&update.display_name)?;
+    repo.save_user(&user).await?;
+    Ok(Profile::from_user(&user, 0))
+}
+
+/// Checks the credentials, failed attempts slowing down further ones. Unknown users
+/// take as long and fail the same way as wrong passwords.
+pub async fn login(
+    repo: &Repository,
+    attempts: &LoginAttempts,
+    form: &LoginForm,
+    address: &str,
+) -> Result<UserData, ServiceError> {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let username = form.username.trim().to_lowercase();
+    let now = now_ms();
+    attempts
+        .attempt(&username, address, now)
+        .map_err(ServiceError::Throttled)?;
+    // Accounts created before usernames were lowercased keep their original case.
+    let typed = form.username.trim();
+    let user = match repo.load_user(typed).await {
+        Ok(user) => Some(user),
+        Err(_) if typed != username => repo.load_user(&username).await.ok(),
+        Err(_) => None,
+    };
+    let verified = match &user {
+        Some(user) => form.verify(user).is_ok(),
+        None => {
+            let dummy = DUMMY_HASH.get_or_init(|| {
+                UserData::from(LoginForm {
+                    username: String::new(),
+                    password: "not a password".to_string(),
+                    remember: false,
+                })
+                .passhash
+            });
+            let _ = form.verify_hash(dummy);
+            false
+        }
+    };
+    match user {
+        Some(mut user) if verified => {
+            attempts.succeed(&username, address);
+            if user.banned {
+                return Err(ServiceError::Banned);
+            }
+            user.last_seen_at = now;
+            repo.save_user(&user).await?;
+            Ok(user)
+        }
+        _ => Err(ServiceError::Unauthorized),
+    }
+}
+
+/// Creates the account, refusing names already taken.
+pub async fn create_user(repo: Repository, mut form: LoginForm) -> Result<UserData, ServiceError> {
+    form.username = accounts::normalize_username(&form.username)?;
+    accounts::check_password(&form.username, &form.password)?;
+    let user = UserData::from(form);
+    repo.create_user(&user).await?;
+    Ok(user)
+}
+
+pub async fn change_password(
+    repo: Repository,
+    user_id: String,
+    change: PasswordChange,
+) -> Result<(), ServiceError> {
+    let mut user = repo.load_user(&user_id).await?;
+    LoginForm {
+        username: user_id.clone(),
+        password: change.current,
+        remember: false,
+    }
+    .verify(&user)?;
+    accounts::check_password(user.login_name(), &change.new)?;
+    user.set_password(&change.new);
+    repo.save_user(&user).await
+}
+
+/// A new guest account, to try the game without signing up. Each address may only
+/// create a few in a row.
+pub async fn create_guest(
+    repo: Repository,
+    attempts: &LoginAttempts,
+    address: &str,
+) -> Result<UserData, ServiceError> {
+    attempts
+        .sign_up_guest(address, now_ms())
+        .map_err(ServiceError::Throttled)?;
+    let (user_id, display_name) = loop {
+        let (user_id, display_name) = accounts::guest_identity();
+        if !repo.user_exists(&user_id) {
+            break (user_id, display_name);
+        }
+    };
+    let mut user = UserData::new(user_id);
+    user.display_name = display_name;
+    user.guest = true;
+    repo.save_user(&user).await?;
+    Ok(user)
+}
+
+/// Turns a guest into a full account logging in with the chosen name. The guest keeps
+/// their id, and so their profile, games and challenges.
+pub async fn upgrade_guest(
+    repo: Repository,
+    user_id: String,
+    mut form: LoginForm,
+) -> Result<UserData, ServiceError> {
+    let mut user = repo.load_user(&user_id).await?;
+    if !user.guest {
+        return Err(AccountError::NotAGuest.into());
+    }
+    form.username = accounts::normalize_username(&form.username)?;
+    accounts::check_password(&form.username, &form.password)?;
+    repo.create_alias(&form.username, &user_id).await?;
+    user.username = Some(form.username);
+    user.guest = false;
+    user.set_password(&form.password);
+    user.last_seen_at = now_ms();
+    if let Err(error) = repo.save_user(&user).await {
+        let _ = repo.unlink_alias(user.login_name());
+        return Err(error);
+    }
+    Ok(user)
+}
+
+/// Whether the user is seated in a game that isn't finished.
+async fn is_playing(repo: &Repository, user_id: &str) -> Result<bool, ServiceError> {
+    let game_list = repo.load_game_list().await?;
+    Ok(game_list.values().any(|game_ref| {
+        game_ref.status != GameStatus::Finished
+            && game_ref
+                .seated_players
+                .iter()
+                .any(|player| player == user_id)
+    }))
+}
+
+/// Deletes guests that have been inactive for `GUEST_TTL_MS`, with no session
+/// left and no game in progress.
+pub async fn clean_up_guests(repo: Repository, sessions: FileSessionStore) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        let Ok(users) = repo.list_users().await else {
+            continue;
+        };
+        let now = now_ms();
+        for user in users {
+            if !user.guest
+                || now - user.last_seen_at < accounts::GUEST_TTL_MS
+                || !sessions.list(&user.id, None).is_empty()
+                || is_playing(&repo, &user.id).await.unwrap_or(true)
+            {
+                continue;
+            }
+            for challenge in &user.challenges {
+                let _ = take_challenge(&repo, &user.id, challenge.id).await;
+            }
+            match repo.unlink_user(&user.id).await {
+                Ok(()) => tracing::info!(user = user.id, "removed inactive guest"),
+                Err(error) => tracing::info!("guest cleanup error: {}", error.to_string()),
+            }
+        }
+    }
+}
+
+/// Deletes the account once the user is in no open or running game, withdrawing
+/// their challenges and leaving the matchmaking queue.
+pub async fn delete_account(
+    repo: Repository,
+    queue: Queue,
+    user_id: String,
+    deletion: AccountDeletion,
+) -> Result<(), ServiceError> {
+    let user = repo.load_user(&user_id).await?;
+    LoginForm {
+        username: user_id.clone(),
+        password: deletion.password,
+        remember: false,
+    }
+    .verify(&user)?;
+    if is_playing(&repo, &user_id).await? {
+        return Err(AccountError::StillPlaying.into());
+    }
+    for challenge in &user.challenges {
+        take_challenge(&repo, &user_id, challenge.id).await?;
+    }
+    queue.leave(&user_id);
+    repo.unlink_user(&user_id).await
+}
+
+pub async fn join_queue(
+    repo: Repository,
+    queue: Queue,
+    user_id: String,
+    scenarios: Vec<i64>,
+) -> Result<(), ServiceError> {
+    if scenarios.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Pick at least one scenario".to_string(),
+        ));
+    }
+    for scenario_id in &scenarios {
+        match get_scenario(*scenario_id) {
+            Some(scenario) if scenario.players.len() == 2 => {}
+            _ => {
+                return Err(ServiceError::BadRequest(format!(
+                    "Scenario {} can't be played through matchmaking",
+                    scenario_id
+                )))
+            }
+        }
+    }
+    let user = repo.load_user(&user_id).await?;
+    if user.guest {
+        return Err(AccountError::GuestNotAllowed.into());
+    }
+    queue.join(QueueEntry {
+        user_id,
+        elo: user.rating.elo,
+        scenarios,
+        joined_at: now_ms(),
+    });
+    Ok(())
+}
+
+pub fn leave_queue(queue: Queue, user_id: String) -> Result<(), ServiceError> {
+    match queue.leave(&user_id) {
+        true => Ok(()),
+        false => Err(ServiceError::NotFound),
+    }
+}
+
+pub fn get_queue_status(queue: Queue, user_id: String) -> QueueStatus {
+    queue.status(&user_id, now_ms())
+}
+
+/// Pairs queued players, creating a rated game with both of them seated.
+pub async fn run_matchmaking(repo: Repository, queue: Queue) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        while let Some(pairing) = matchmaking::find_pairing(&queue.entries(), now_ms()) {
+            match create_rated_game(&repo, &pairing).await {
+                Ok(game_id) => queue.set_matched(&pairing, game_id),
+                Err(error) => {
+                    tracing::info!("matchmaking error: {}", error.to_string());
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn create_rated_game(
+    repo: &Repository,
+    pairing: &Pairing,
+) -> Result<uuid::Uuid, ServiceError> {
+    let game_id = create_seated_game(
+        repo,
+        &pairing.players,
+        pairing.scenario,
+        TimeControl::default(),
+        false,
+    )
+    .await?;
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = repo.load_game(&game_id).await?;
+    game.rated = true;
+    repo.save_game(&game).await?;
+    Ok(game_id)
+}
+
+/// Creates a game with the players already seated, in order, the first one being
+/// its creator.
+async fn create_seated_game(
+    repo: &Repository,
+    players: &[String],
+    scenario_id: i64,
+    time_control: TimeControl,
+    private: bool,
+) -> Result<uuid::Uuid, ServiceError> {
+    let game_id = new_game(
+        repo.clone(),
+        players[0].clone(),
+        scenario_id,
+        time_control,
+        private,
+        false,
+    )
+    .await?;
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = repo.load_game(&game_id).await?;
+    for (seat, user_id) in game.seats.iter_mut().zip(players) {
+        seat.user_id = Some(user_id.clone());
+    }
+    game.update_phase(now_ms());
+    repo.save_game(&game).await?;
+    update_seats(repo, &game).await?;
+    Ok(game_id)
+}
+
+pub async fn challenge_player(
+    repo: Repository,
+    user_id: String,
+    req: ChallengeRequest,
+) -> Result<Challenge, ServiceError> {
+    if req.opponent == user_id {
+        return Err(ServiceError::BadRequest(
+            "Can't challenge yourself".to_string(),
+        ));
+    }
+    match get_scenario(req.scenario_id) {
+        Some(scenario) if scenario.players.len() == 2 => {}
+        _ => {
+            return Err(ServiceError::BadRequest(
+                "Challenges are for two player scenarios".to_string(),
+            ))
+        }
+    }
+    let mut opponent = repo.load_user(&req.opponent).await?;
+    let mut challenger = repo.load_user(&user_id).await?;
+    let challenge = Challenge {
+        id: uuid::Uuid::new_v4(),
+        from: user_id,
+        to: req.opponent,
+        scenario_id: req.scenario_id,
+        time_control: req.time_control,
+        created_at: now_ms(),
+    };
+    opponent.challenges.push(challenge.clone());
+    challenger.challenges.push(challenge.clone());
+    repo.save_user(&opponent).await?;
+    repo.save_user(&challenger).await?;
+    Ok(challenge)
+}
+
+pub async fn get_challenges(
+    repo: Repository,
+    user_id: String,
+) -> Result<Vec<Challenge>, ServiceError> {
+    Ok(repo.load_user(&user_id).await?.challenges)
+}
+
+/// Removes the challenge from both players.
+async fn take_challenge(
+    repo: &Repository,
+    user_id: &str,
+    challenge_id: uuid::Uuid,
+) -> Result<Challenge, ServiceError> {
+    let challenge = repo
+        .load_user(user_id)
+        .await?
+        .challenges
+        .into_iter()
+        .find(|challenge| challenge.id == challenge_id)
+        .ok_or(ServiceError::NotFound)?;
+    for player in [&challenge.from, &challenge.to] {
+        let mut user = repo.load_user(player).await?;
+        user.challenges.retain(|c| c.id != challenge_id);
+        repo.save_user(&user).await?;
+    }
+    Ok(challenge)
+}
+
+/// Starts a private game between the challenger and the user.
+pub async fn accept_challenge(
+    repo: Repository,
+    user_id: String,
+    challenge_id: uuid::Uuid,
+) -> Result<uuid::Uuid, ServiceError> {
+    let challenge = repo
+        .load_user(&user_id)
+        .await?
+        .challenges
+        .into_iter()
+        .find(|challenge| challenge.id == challenge_id)
+        .ok_or(ServiceError::NotFound)?;
+    if challenge.to != user_id {
+        return Err(ServiceError::Unauthorized);
+    }
+    take_challenge(&repo, &user_id, challenge_id).await?;
+    create_seated_game(
+        &repo,
+        &[challenge.from, challenge.to],
+        challenge.scenario_id,
+        challenge.time_control,
+        true,
+    )
+    .await
+}
+
+/// Declines a received challenge, or withdraws a sent one.
+pub async fn decline_challenge(
+    repo: Repository,
+    user_id: String,
+    challenge_id: uuid::Uuid,
+) -> Result<(), ServiceError> {
+    take_challenge(&repo, &user_id, challenge_id).await?;
+    Ok(())
+}
+
+/// Sends every player the view of their seat. Users playing several seats on one
+/// device only ever get the active seat's, through `tick` and its handoff.
+pub async fn broadcast(events: &Events, game: &Game) {
+    let players: HashSet<(String, i64)> = game
+        .entities
+        .values()
+        .flatten()
+        .map(|e| (e.user_id.clone(), e.scenario_player_index))
+        .collect();
+    let mut shared_devices = HashSet::new();
+    for (user_id, index) in players {
+        if game.shares_device(&user_id) {
+            shared_devices.insert(user_id);
+            continue;
+        }
+        if let Ok(gamestate) = get_gamestate_for(game, &Viewer::Seat(index)) {
+            let _res = events.send_event(gamestate, game.id, user_id).await;
+        }
+    }
+    if game
+        .get_trait_entity()
+        .is_ok_and(|active| shared_devices.contains(&active.user_id))
+    {
+        let _res = tick(events.clone(), game).await;
+    }
+}
+
+pub async fn watch_clocks(repo: Repository, events: Events) {
+    let mut interval = tokio::time::interval(Duration::from_millis(500));
+    loop {
+        interval.tick().await;
+        let Ok(game_list) = repo.load_game_list().await else {
+            continue;
+        };
+        for game_ref in game_list.values() {
+            let result = match game_ref.status {
+                GameStatus::Running => check_clock(&repo, &events, game_ref.game_id).await,
+                GameStatus::Open if game_ref.deployment_deadline.is_some() || game_ref.drafting => {
+                    check_deployment(&repo, &events, game_ref.game_id).await
+                }
+                _ => continue,
+            };
+            if let Err(error) = result {
+                tracing::info!("clock error: {}", error.to_string());
+            }
+        }
+    }
+}
+
+/// Acts on behalf of the active player once they run out of time: the default ability
+/// is played for a per turn timeout, and the seat loses when its time bank is empty.
+pub async fn check_clock(
+    repo: &Repository,
+    events: &Events,
+    game_id: uuid::Uuid,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = repo.load_game(&game_id).await?;
+    if game.result.is_some() || game.turn_time_left(now_ms()) != Some(0) {
+        return Ok(());
+    }
+    let entity = game.get_trait_entity()?.clone();
+    let (default_ability, max_timeouts) = match game.time_control.clone() {
+        TimeControl::Unlimited => return Ok(()),
+        TimeControl::ChessClock { .. } => {
+            let index = entity.scenario_player_index;
+            return eliminate_seat(repo, events, &mut game, index, EndReason::OutOfTime).await;
+        }
+        TimeControl::PerTurn {
+            default_ability,
+            max_timeouts,
+            ..
+        } => (default_ability, max_timeouts),
+    };
+    let timeouts = game
+        .timeouts
+        .entry(entity.scenario_player_index)
+        .or_default();
+    *timeouts += 1;
+    if *timeouts >= max_timeouts {
+        let index = entity.scenario_player_index;
+        return eliminate_seat(repo, events, &mut game, index, EndReason::Timeout).await;
+    }
+    let actions = legal_actions(&game);
+    // Targets come in no particular order, so only the unit's own tile is used.
+    let action = actions
+        .iter()
+        .find(|a| a.ability == default_ability && a.target == entity.coords)
+        .or_else(|| actions.iter().find(|a| a.ability == AbilityName::Wait))
+        .cloned()
+        .unwrap_or(GameAction {
+            entity_id: entity.id,
+            ability: AbilityName::Wait,
+            target: entity.coords,
+        });
+    play_action(repo, events, &mut game, action).await
+}
+
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Step by step states of the game, through the eyes of `user_id`'s seat,
+/// or of everyone once the game is over.
+pub async fn get_replay(
+    repo: Repository,
+    game_id: uuid::Uuid,
+    user_id: String,
+) -> Result<Vec<Gamestate>, ServiceError> {
+    let game_list = repo.load_game_list().await?;
+    let game_ref = game_list.get(&game_id).ok_or(ServiceError::NotFound)?;
+    let records = repo.load_history(&game_id).await?;
+    let viewer = if game_ref.status == GameStatus::Finished {
+        Viewer::Omniscient
+    } else {
+        records
+            .iter()
+            .find_map(|record| match record {
+                HistoryRecord::Deployed { entities } => entities
+                    .iter()
+                    .find(|e| e.user_id == user_id)
+                    .map(|e| Viewer::Seat(e.scenario_player_index)),
+                _ => None,
+            })
+            .ok_or(ServiceError::Unauthorized)?
+    };
+    Ok(history::replay(game_id, &records)?
+        .iter()
+        .filter_map(|state| get_gamestate_for(state, &viewer).ok())
+        .collect())
+}
+
+async fn audit(repo: &Repository, admin: &str, action: AdminAction) -> Result<(), ServiceError> {
+    tracing::info!(admin = admin, action = ?action, "admin action");
+    repo.append_audit(&AuditRecord {
+        at: now_ms(),
+        admin: admin.to_string(),
+        action,
+    })
+    .await
+}
+
+pub async fn admin_list_games(repo: Repository) -> Result<Vec<GameRef>, ServiceError> {
+    Ok(repo.load_game_list().await?.into_values().collect())
+}
+
+pub async fn admin_inspect_game(
+    repo: Repository,
+    game_id: uuid::Uuid,
+) -> Result<GameInspection, ServiceError> {
+    let game_list = repo.load_game_list().await?;
+    let game_ref = game_list.get(&game_id).ok_or(ServiceError::NotFound)?;
+    let game = repo.load_game(&game_id).await?;
+    Ok(GameInspection {
+        game: game_ref.clone(),
+        gamestate: get_gamestate_for(&game, &Viewer::Omniscient).ok(),
+        deployments: game.deployments,
+    })
+}
+
+/// Ends the game with the given winners, or aborts it if it hasn't started.
+pub async fn admin_finish_game(
+    repo: Repository,
+    events: Events,
+    admin: String,
+    game_id: uuid::Uuid,
+    winners: Vec<i64>,
+) -> Result<GameResult, ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    let mut game = repo.load_game(&game_id).await?;
+    if game.result.is_some() {
+        return Err(ServiceError::BadRequest("Game is already over".to_string()));
+    }
+    if winners
+        .iter()
+        .any(|seat| *seat < 0 || *seat as usize >= game.seats.len())
+    {
+        return Err(ServiceError::BadRequest("No such seat".to_string()));
+    }
+    let result = match game.has_started() {
+        true => GameResult {
+            winners: winners.clone(),
+            reason: EndReason::Adjudicated,
+        },
+        false => GameResult {
+            winners: vec![],
+            reason: EndReason::Aborted,
+        },
+    };
+    finish_game(&repo, &events, &mut game, result.clone()).await?;
+    audit(&repo, &admin, AdminAction::FinishGame { game_id, winners }).await?;
+    Ok(result)
+}
+
+pub async fn admin_delete_game(
+    repo: Repository,
+    admin: String,
+    game_id: uuid::Uuid,
+) -> Result<(), ServiceError> {
+    let _lock = repo.lock_game(&game_id).await;
+    repo.update_game_list(|game_list| game_list.remove(&game_id).ok_or(ServiceError::NotFound))
+        .await?;
+    repo.unlink_game(&game_id).await?;
+    audit(&repo, &admin, AdminAction::DeleteGame { game_id }).await
+}
+
+pub async fn admin_list_users(repo: Repository) -> Result<Vec<UserSummary>, ServiceError> {
+    Ok(repo
+        .list_users()
+        .await?
+        .iter()
+        .map(UserSummary::from)
+        .collect())
+}
+
+pub async fn admin_reset_password(
+    repo: Repository,
+    admin: String,
+    user_id: String,
+    password: String,
+) -> Result<(), ServiceError> {
+    let mut user = repo.load_user(&user_id).await?;
+    accounts::check_password(&user.login_name().to_lowercase(), &password)?;
+    user.set_password(&password);
+    repo.save_user(&user).await?;
+    audit(&repo, &admin, AdminAction::ResetPassword { user_id }).await
+}
+
+/// Banned users can't log in, and their open sessions stop working.
+pub async fn admin_set_banned(
+    repo: Repository,
+    admin: String,
+    user_id: String,
+    banned: bool,
+) -> Result<UserSummary, ServiceError> {
+    if banned && admin == user_id {
+        return Err(ServiceError::BadRequest("Can't ban yourself".to_string()));
+    }
+    let mut user = repo.load_user(&user_id).await?;
+    user.banned = banned;
+    repo.save_user(&user).await?;
+    let action = match banned {
+        true => AdminAction::Ban { user_id },
+        false => AdminAction::Unban { user_id },
+    };
+    audit(&repo, &admin, action).await?;
+    Ok(UserSummary::from(&user))
+}
+
+pub async fn admin_set_role(
+    repo: Repository,
+    admin: String,
+    user_id: String,
+    role: String,
+    granted: bool,
+) -> Result<UserSummary, ServiceError> {
+    if !granted && admin == user_id && role == ADMIN_ROLE {
+        return Err(ServiceError::BadRequest(
+            "Can't revoke your own admin role".to_string(),
+        ));
+    }
+    let mut user = repo.load_user(&user_id).await?;
+    user.roles.retain(|r| r != &role);
+    if granted {
+        user.roles.push(role.clone());
+    }
+    repo.save_user(&user).await?;
+    let action = match granted {
+        true => AdminAction::GrantRole { user_id, role },
+        false => AdminAction::RevokeRole { user_id, role },
+    };
+    audit(&repo, &admin, action).await?;
+    Ok(UserSummary::from(&user))
+}
+
+pub async fn admin_get_audit(repo: Repository) -> Result<Vec<AuditRecord>, ServiceError> {
+    repo.load_audit().await
+}
+
+pub async fn list_tokens(
+    repo: Repository,
+    user_id: String,
+) -> Result<Vec<TokenInfo>, ServiceError> {
+    let user = repo.load_user(&user_id).await?;
+    Ok(user.tokens.iter().map(TokenInfo::from).collect())
+}
+
+pub async fn create_token(
+    repo: Repository,
+    user_id: String,
+    req: TokenRequest,
+) -> Result<CreatedToken, ServiceError> {
+    let name = req.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(ServiceError::BadRequest(
+            "Token name must be 1 to 64 characters long".to_string(),
+        ));
+    }
+    let mut user = repo.load_user(&user_id).await?;
+    if user.guest {
+        return Err(AccountError::GuestNotAllowed.into());
+    }
+    if user.tokens.len() >= MAX_TOKENS {
+        return Err(ServiceError::BadRequest(format!(
+            "No more than {} tokens per user",
+            MAX_TOKENS
+        )));
+    }
+    let (token, full) = ApiToken::generate(&user_id, name, req.scope, now_ms());
+    let info = TokenInfo::from(&token);
+    user.tokens.push(token);
+    repo.save_user(&user).await?;
+    Ok(CreatedToken { info, token: full })
+}
+
+pub async fn revoke_token(
+    repo: Repository,
+    user_id: String,
+    token_id: uuid::Uuid,
+) -> Result<(), ServiceError> {
+    let mut user = repo.load_user(&user_id).await?;
+    let count = user.tokens.len();
+    user.tokens.retain(|token| token.id != token_id);
+    if user.tokens.len() == count {
+        return Err(ServiceError::NotFound);
+    }
+    repo.save_user(&user).await
+}
+
+/// The user and scope of a bearer token, unless it is unknown, revoked or its
+/// owner banned.
+pub async fn authenticate_token(
+    repo: &Repository,
+    token: &str,
+) -> Result<(String, TokenScope), ServiceError> {
+    let (user_id, token_id, secret) =
+        tokens::parse_token(token).ok_or(ServiceError::Unauthorized)?;
+    let user = repo
+        .load_user(user_id)
+        .await
+        .map_err(|_| ServiceError::Unauthorized)?;
+    match user.tokens.iter().find(|token| token.id == token_id) {
+        Some(token) if !user.banned && token.verify(secret) => Ok((user.id, token.scope)),
+        _ => Err(ServiceError::Unauthorized),
+    }
 }
@@ -1,40 +1,90 @@
+use crate::accounts::AccountError;
+use crate::admin::AuditRecord;
+use crate::history::HistoryRecord;
 use crate::schemas::{Game, GameRef, UserData};
 use crate::services::ServiceError;
 use dashmap::{self, DashMap};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
+use std::io::Cursor;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
 
 #[derive(Debug, Clone)]
 pub struct Repository {
-    pub game_cache: DashMap<uuid::Uuid, Game>,
-    pub user_cache: DashMap<String, UserData>,
+    pub game_cache: Arc<DashMap<uuid::Uuid, Game>>,
+    pub user_cache: Arc<DashMap<String, UserData>>,
+    game_locks: Arc<DashMap<uuid::Uuid, Arc<Mutex<()>>>>,
+    game_list_lock: Arc<Mutex<()>>,
 }
 
 impl Repository {
     pub async fn new() -> Self {
         Self {
-            game_cache: DashMap::new(),
-            user_cache: DashMap::new(),
+            game_cache: Arc::new(DashMap::new()),
+            user_cache: Arc::new(DashMap::new()),
+            game_locks: Arc::new(DashMap::new()),
+            game_list_lock: Arc::new(Mutex::new(())),
         }
     }
+
+    /// Held from loading a game to saving it, so that concurrent changes to the same
+    /// game don't overwrite each other. Not reentrant, so only taken by the services
+    /// called from outside.
+    pub async fn lock_game(&self, game_id: &uuid::Uuid) -> OwnedMutexGuard<()> {
+        let lock = self.game_locks.entry(*game_id).or_default().clone();
+        lock.lock_owned().await
+    }
+
     pub fn game_file_name(game_id: &uuid::Uuid) -> String {
         format!("game_{}.mp", game_id)
     }
 
+    pub fn history_file_name(game_id: &uuid::Uuid) -> String {
+        format!("history_{}.mp", game_id)
+    }
+
     pub fn user_file_name(user_id: &str) -> String {
         format!("user_{}.mp", user_id)
     }
 
-    pub async fn save_game_list(
+    /// Ids that can't escape the store directory.
+    fn is_safe_user_id(user_id: &str) -> bool {
+        !user_id.is_empty() && !user_id.contains(['/', '\\', '\0'])
+    }
+
+    pub fn user_exists(&self, user_id: &str) -> bool {
+        self.user_cache.contains_key(user_id)
+            || std::path::Path::new(&Self::user_file_name(user_id)).exists()
+    }
+
+    async fn save_game_list(
         &self,
         game_list: HashMap<uuid::Uuid, GameRef>,
     ) -> Result<(), ServiceError> {
-        let file = fs::File::create("game_list.mp")?;
+        let file = fs::File::create("game_list.mp.tmp")?;
         game_list.serialize(&mut rmp_serde::Serializer::new(file))?;
+        fs::rename("game_list.mp.tmp", "game_list.mp")?;
         Ok(())
     }
 
+    /// Loads, changes and saves the game list, one change at a time. Taken after the
+    /// lock of a game, never before.
+    pub async fn update_game_list<T>(
+        &self,
+        change: impl FnOnce(&mut HashMap<uuid::Uuid, GameRef>) -> Result<T, ServiceError>,
+    ) -> Result<T, ServiceError> {
+        let _lock = self.game_list_lock.lock().await;
+        let mut game_list = match fs::metadata("game_list.mp") {
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            _ => self.load_game_list().await?,
+        };
+        let value = change(&mut game_list)?;
+        self.save_game_list(game_list).await?;
+        Ok(value)
+    }
+
     pub async fn load_game_list(&self) -> Result<HashMap<uuid::Uuid, GameRef>, ServiceError> {
         let file = fs::File::open("game_list.mp")?;
         Ok(rmp_serde::from_read(file)?)
@@ -59,17 +109,96 @@ impl Repository {
 
     pub async fn unlink_game(&self, game_id: &uuid::Uuid) -> Result<(), ServiceError> {
         self.game_cache.remove(game_id);
+        self.game_locks.remove(game_id);
+        let _res = fs::remove_file(Self::history_file_name(game_id));
         Ok(fs::remove_file(Self::game_file_name(game_id))?)
     }
 
+    pub async fn append_history(
+        &self,
+        game_id: &uuid::Uuid,
+        record: &HistoryRecord,
+    ) -> Result<(), ServiceError> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::history_file_name(game_id).as_str())?;
+        record.serialize(&mut rmp_serde::Serializer::new(file))?;
+        Ok(())
+    }
+
+    pub async fn load_history(
+        &self,
+        game_id: &uuid::Uuid,
+    ) -> Result<Vec<HistoryRecord>, ServiceError> {
+        let bytes = fs::read(Self::history_file_name(game_id).as_str())?;
+        let mut cursor = Cursor::new(bytes.as_slice());
+        let mut records = vec![];
+        while (cursor.position() as usize) < bytes.len() {
+            records.push(HistoryRecord::deserialize(
+                &mut rmp_serde::Deserializer::new(&mut cursor),
+            )?);
+        }
+        Ok(records)
+    }
+
     pub async fn save_user(&self, user: &UserData) -> Result<(), ServiceError> {
+        if !Self::is_safe_user_id(&user.id) {
+            return Err(ServiceError::BadRequest("Invalid user id".to_string()));
+        }
         let file = fs::File::create(Self::user_file_name(&user.id).as_str())?;
         user.serialize(&mut rmp_serde::Serializer::new(file))?;
         self.user_cache.insert(user.id.clone(), user.clone());
         Ok(())
     }
 
+    /// Saves a new user, failing if the id is already taken, even by a concurrent
+    /// sign-up.
+    pub async fn create_user(&self, user: &UserData) -> Result<(), ServiceError> {
+        Self::write_new_user(&user.id, user)?;
+        self.user_cache.insert(user.id.clone(), user.clone());
+        Ok(())
+    }
+
+    /// Reserves the name for the user, so they can log in with it while keeping their
+    /// id. Fails like `create_user` if the name is taken.
+    pub async fn create_alias(&self, name: &str, user_id: &str) -> Result<(), ServiceError> {
+        let mut alias = UserData::new(name.to_string());
+        alias.alias_of = Some(user_id.to_string());
+        Self::write_new_user(name, &alias)
+    }
+
+    fn write_new_user(user_id: &str, user: &UserData) -> Result<(), ServiceError> {
+        if !Self::is_safe_user_id(user_id) {
+            return Err(ServiceError::BadRequest("Invalid user id".to_string()));
+        }
+        let file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(Self::user_file_name(user_id).as_str())
+        {
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(AccountError::UsernameTaken.into())
+            }
+            file => file?,
+        };
+        user.serialize(&mut rmp_serde::Serializer::new(file))?;
+        Ok(())
+    }
+
+    /// Loads the user by id, or by the name an upgraded guest picked.
     pub async fn load_user(&self, user_id: &str) -> Result<UserData, ServiceError> {
+        let user = self.read_user(user_id)?;
+        match &user.alias_of {
+            Some(target) => self.read_user(target),
+            None => Ok(user),
+        }
+    }
+
+    fn read_user(&self, user_id: &str) -> Result<UserData, ServiceError> {
+        if !Self::is_safe_user_id(user_id) {
+            return Err(ServiceError::NotFound);
+        }
         match self.user_cache.get(user_id) {
             None => {
                 let file = fs::File::open(Self::user_file_name(user_id).as_str())?;
@@ -79,7 +208,65 @@ impl Repository {
         }
     }
 
+    /// Every user with a file in the store, skipping the unreadable ones and the
+    /// names reserved by upgraded guests.
+    pub async fn list_users(&self) -> Result<Vec<UserData>, ServiceError> {
+        let mut users = vec![];
+        for entry in fs::read_dir(".")? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            let Some(user_id) = name
+                .strip_prefix("user_")
+                .and_then(|name| name.strip_suffix(".mp"))
+            else {
+                continue;
+            };
+            match self.read_user(user_id) {
+                Ok(user) if user.alias_of.is_some() => {}
+                Ok(user) => users.push(user),
+                Err(error) => tracing::info!("can't read {}: {}", name, error.to_string()),
+            }
+        }
+        Ok(users)
+    }
+
+    pub async fn append_audit(&self, record: &AuditRecord) -> Result<(), ServiceError> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open("audit.mp")?;
+        record.serialize(&mut rmp_serde::Serializer::new(file))?;
+        Ok(())
+    }
+
+    pub async fn load_audit(&self) -> Result<Vec<AuditRecord>, ServiceError> {
+        let bytes = match fs::read("audit.mp") {
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            bytes => bytes?,
+        };
+        let mut cursor = Cursor::new(bytes.as_slice());
+        let mut records = vec![];
+        while (cursor.position() as usize) < bytes.len() {
+            records.push(AuditRecord::deserialize(
+                &mut rmp_serde::Deserializer::new(&mut cursor),
+            )?);
+        }
+        Ok(records)
+    }
+
+    /// Removes the user along with the name they reserved, if any.
     pub async fn unlink_user(&self, user_id: &str) -> Result<(), ServiceError> {
+        let user = self.read_user(user_id)?;
+        if let Some(name) = &user.username {
+            self.unlink_alias(name)?;
+        }
+        self.user_cache.remove(user_id);
         Ok(fs::remove_file(Self::user_file_name(user_id))?)
     }
+
+    pub fn unlink_alias(&self, name: &str) -> Result<(), ServiceError> {
+        if !Self::is_safe_user_id(name) {
+            return Err(ServiceError::NotFound);
+        }
+        Ok(fs::remove_file(Self::user_file_name(name))?)
+    }
 }
@@ -1,28 +1,58 @@
-use std::sync::{mpsc::Sender, Arc};
+use std::{
+    sync::{mpsc::Sender, Arc},
+    time::{Duration, Instant},
+};
 
 use dashmap::DashMap;
+use tokio::sync::mpsc::UnboundedSender;
 
-use crate::{schemas::Gamestate, services::ServiceError};
+use crate::{
+    schemas::{Gamestate, ServerMessage},
+    services::{ServiceError, Viewer},
+};
+
+struct Spectator {
+    id: uuid::Uuid,
+    viewer: Viewer,
+    sender: UnboundedSender<Gamestate>,
+}
 
 #[derive(Clone)]
 pub struct Events {
-    inner: Arc<DashMap<(uuid::Uuid, String), Vec<Sender<Gamestate>>>>,
+    inner: Arc<DashMap<(uuid::Uuid, String), Vec<Sender<ServerMessage>>>>,
+    spectators: Arc<DashMap<uuid::Uuid, Vec<Spectator>>>,
+    /// Open player sockets, by game and user.
+    connections: Arc<DashMap<(uuid::Uuid, String), usize>>,
+    /// When the last socket of each user closed.
+    disconnected_at: Arc<DashMap<(uuid::Uuid, String), Instant>>,
+    started_at: Instant,
 }
 
 impl Events {
     pub async fn send_event(
         &self,
-        gs: Gamestate,
+        mut gs: Gamestate,
         game_id: uuid::Uuid,
         user_id: String,
     ) -> Result<(), ServiceError> {
         tracing::info!("send_event");
+        gs.spectators = self.spectator_count(game_id);
+        self.send_message(ServerMessage::Gamestate(Box::new(gs)), game_id, user_id)
+            .await
+    }
+
+    pub async fn send_message(
+        &self,
+        message: ServerMessage,
+        game_id: uuid::Uuid,
+        user_id: String,
+    ) -> Result<(), ServiceError> {
         match self.inner.get_mut(&(game_id, user_id.clone())) {
             None => Err(ServiceError::QueueError(
                 "Unable to queue event".to_string(),
             )),
             Some(mut senders) => {
-                senders.retain(|sender| sender.send(gs.clone()).is_ok());
+                senders.retain(|sender| sender.send(message.clone()).is_ok());
                 if senders.len() > 0 {
                     return Ok(());
                 }
@@ -37,8 +67,13 @@ impl Events {
         &self,
         game_id: uuid::Uuid,
         user_id: String,
-        sender: Sender<Gamestate>,
+        sender: Sender<ServerMessage>,
     ) -> Result<(), ()> {
+        *self
+            .connections
+            .entry((game_id, user_id.clone()))
+            .or_default() += 1;
+        self.disconnected_at.remove(&(game_id, user_id.clone()));
         let existing = self.inner.get_mut(&(game_id, user_id.clone()));
         match existing {
             None => {
@@ -50,9 +85,97 @@ impl Events {
         }
         Ok(())
     }
+
+    /// Called when one of the user's sockets closes.
+    pub fn disconnect(&self, game_id: uuid::Uuid, user_id: String) {
+        let key = (game_id, user_id);
+        if self
+            .connections
+            .remove_if_mut(&key, |_, count| {
+                *count = count.saturating_sub(1);
+                *count == 0
+            })
+            .is_some()
+        {
+            self.disconnected_at.insert(key, Instant::now());
+        }
+    }
+
+    /// How long the user has had no socket open on the game, or `None` while they do.
+    /// Users not seen since the server started count from the start.
+    pub fn disconnected_for(&self, game_id: uuid::Uuid, user_id: &str) -> Option<Duration> {
+        let key = (game_id, user_id.to_string());
+        if self.connections.get(&key).is_some_and(|count| *count > 0) {
+            return None;
+        }
+        let since = self
+            .disconnected_at
+            .get(&key)
+            .map(|at| *at)
+            .unwrap_or(self.started_at);
+        Some(since.elapsed())
+    }
+
+    /// Returns the id to remove the spectator with once they leave.
+    pub async fn register_spectator(
+        &self,
+        game_id: uuid::Uuid,
+        viewer: Viewer,
+        sender: UnboundedSender<Gamestate>,
+    ) -> uuid::Uuid {
+        let id = uuid::Uuid::new_v4();
+        self.spectators
+            .entry(game_id)
+            .or_default()
+            .push(Spectator { id, viewer, sender });
+        id
+    }
+
+    /// Called when a spectator's socket closes.
+    pub fn remove_spectator(&self, game_id: uuid::Uuid, id: uuid::Uuid) {
+        self.spectators.remove_if_mut(&game_id, |_, spectators| {
+            spectators.retain(|spectator| spectator.id != id);
+            spectators.is_empty()
+        });
+    }
+
+    pub fn spectator_count(&self, game_id: uuid::Uuid) -> usize {
+        self.spectators
+            .get(&game_id)
+            .map(|spectators| spectators.len())
+            .unwrap_or(0)
+    }
+
+    /// Sends each spectator of the game the state computed for its point of view,
+    /// forgetting about the ones who left.
+    pub async fn send_spectators(
+        &self,
+        game_id: uuid::Uuid,
+        gamestate_for: impl Fn(&Viewer) -> Option<Gamestate>,
+    ) {
+        let count = self.spectator_count(game_id);
+        if let Some(mut spectators) = self.spectators.get_mut(&game_id) {
+            spectators.retain(|spectator| match gamestate_for(&spectator.viewer) {
+                None => true,
+                Some(mut gs) => {
+                    gs.spectators = count;
+                    spectator.sender.send(gs).is_ok()
+                }
+            });
+        }
+    }
+
+    pub fn remove_spectators(&self, game_id: uuid::Uuid) {
+        self.spectators.remove(&game_id);
+    }
+
     pub fn new() -> Self {
         return Self {
             inner: Arc::new(DashMap::new()),
+            spectators: Arc::new(DashMap::new()),
+            connections: Arc::new(DashMap::new()),
+            disconnected_at: Arc::new(DashMap::new()),
+            started_at: Instant::now(),
         };
     }
 }
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+
+use crate::throttle::{Failures, ThrottlePolicy, ADDRESS_POLICY, GUEST_POLICY, USERNAME_POLICY};
+
+/// Keys tracked beyond which stale ones are dropped.
+const PRUNE_ABOVE: usize = 10_000;
+
+/// Recent failed logins, by username and by client address, and guest sign-ups by
+/// address.
+#[derive(Clone, Default)]
+pub struct LoginAttempts {
+    usernames: Arc<DashMap<String, Failures>>,
+    addresses: Arc<DashMap<String, Failures>>,
+    guests: Arc<DashMap<String, Failures>>,
+}
+
+impl LoginAttempts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the attempt as failed before the password is even checked, so that
+    /// concurrent attempts can't all get past the throttle; `succeed` takes it back.
+    /// Errs with the milliseconds to wait when throttled.
+    pub fn attempt(&self, username: &str, address: &str, now: i64) -> Result<(), i64> {
+        Self::prune(&self.usernames, &USERNAME_POLICY, now);
+        Self::prune(&self.addresses, &ADDRESS_POLICY, now);
+        // Always locked in this order, the username first.
+        let mut by_username = self.usernames.entry(username.to_string()).or_default();
+        let mut by_address = self.addresses.entry(address.to_string()).or_default();
+        let wait = by_username
+            .retry_after(&USERNAME_POLICY, now)
+            .max(by_address.retry_after(&ADDRESS_POLICY, now));
+        if let Some(wait) = wait {
+            return Err(wait);
+        }
+        by_username.fail(&USERNAME_POLICY, now);
+        by_address.fail(&ADDRESS_POLICY, now);
+        Ok(())
+    }
+
+    /// Forgets the failures of the username, and the attempt from the address.
+    pub fn succeed(&self, username: &str, address: &str) {
+        self.usernames.remove(username);
+        if let Some(mut failures) = self.addresses.get_mut(address) {
+            failures.count = failures.count.saturating_sub(1);
+        }
+    }
+
+    /// Counts a guest sign-up from the address, or errs with the milliseconds to wait.
+    pub fn sign_up_guest(&self, address: &str, now: i64) -> Result<(), i64> {
+        Self::prune(&self.guests, &GUEST_POLICY, now);
+        let mut by_address = self.guests.entry(address.to_string()).or_default();
+        if let Some(wait) = by_address.retry_after(&GUEST_POLICY, now) {
+            return Err(wait);
+        }
+        by_address.fail(&GUEST_POLICY, now);
+        Ok(())
+    }
+
+    fn prune(failures: &DashMap<String, Failures>, policy: &ThrottlePolicy, now: i64) {
+        if failures.len() > PRUNE_ABOVE {
+            failures.retain(|_, failures| !failures.is_stale(policy, now));
+        }
+    }
+}
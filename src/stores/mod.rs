@@ -1,2 +1,5 @@
 pub mod database;
 pub mod events;
+pub mod login_attempts;
+pub mod queue;
+pub mod sessions;
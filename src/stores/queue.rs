@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+
+use crate::matchmaking::{search_range, Pairing, QueueEntry, QueueStatus};
+
+/// Players waiting for an opponent, and the games found for them.
+#[derive(Clone, Default)]
+pub struct Queue {
+    entries: Arc<DashMap<String, QueueEntry>>,
+    matched: Arc<DashMap<String, uuid::Uuid>>,
+}
+
+impl Queue {
+    pub fn join(&self, entry: QueueEntry) {
+        self.matched.remove(&entry.user_id);
+        self.entries.insert(entry.user_id.clone(), entry);
+    }
+
+    pub fn leave(&self, user_id: &str) -> bool {
+        self.entries.remove(user_id).is_some()
+    }
+
+    pub fn entries(&self) -> Vec<QueueEntry> {
+        self.entries.iter().map(|entry| entry.clone()).collect()
+    }
+
+    pub fn status(&self, user_id: &str, now: i64) -> QueueStatus {
+        if let Some(game_id) = self.matched.get(user_id) {
+            return QueueStatus::Matched { game_id: *game_id };
+        }
+        match self.entries.get(user_id) {
+            Some(entry) => QueueStatus::Searching {
+                since: entry.joined_at,
+                range: search_range(now - entry.joined_at),
+            },
+            None => QueueStatus::Idle,
+        }
+    }
+
+    pub fn set_matched(&self, pairing: &Pairing, game_id: uuid::Uuid) {
+        for user_id in &pairing.players {
+            self.entries.remove(user_id);
+            self.matched.insert(user_id.clone(), game_id);
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
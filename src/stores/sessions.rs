@@ -0,0 +1,222 @@
+use std::{collections::HashMap, fs, io::ErrorKind, sync::Arc};
+
+use axum::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tower_sessions::{
+    cookie::time::{Duration, OffsetDateTime},
+    session::{Id, Record},
+    session_store, SessionStore,
+};
+
+const SESSIONS_FILE: &str = "sessions.mp";
+/// How often the expiry of an active session is pushed back, rather than on every
+/// request.
+const REFRESH_INTERVAL: Duration = Duration::hours(1);
+/// Changes made within this delay are written to disk together.
+const WRITE_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Inactivity after which a session expires, `SESSION_TTL_DAYS` or a day.
+pub fn session_ttl() -> Duration {
+    days_from_env("SESSION_TTL_DAYS", 1)
+}
+
+/// Same for "remember me" logins, `REMEMBER_ME_TTL_DAYS` or 30 days.
+fn remember_me_ttl() -> Duration {
+    days_from_env("REMEMBER_ME_TTL_DAYS", 30)
+}
+
+pub fn ttl(remember: bool) -> Duration {
+    if remember {
+        remember_me_ttl()
+    } else {
+        session_ttl()
+    }
+}
+
+fn days_from_env(name: &str, default: i64) -> Duration {
+    let days = std::env::var(name)
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(default);
+    Duration::days(days)
+}
+
+/// Sessions kept in memory and written to disk alongside the games and users, so
+/// they survive restarts.
+#[derive(Clone, Debug, Default)]
+pub struct FileSessionStore {
+    sessions: Arc<DashMap<Id, Record>>,
+    /// Sessions removed before they expired, with their expiry, so that a request
+    /// still in flight can't save them back.
+    revoked: Arc<DashMap<Id, OffsetDateTime>>,
+    /// Wakes the writer up when sessions change.
+    changed: Arc<Notify>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub id: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub current: bool,
+}
+
+impl FileSessionStore {
+    /// Loads the sessions saved on disk, dropping the expired ones, and starts writing
+    /// changes back.
+    pub fn load() -> Self {
+        let store = Self::default();
+        let saved: HashMap<Id, Record> = match fs::File::open(SESSIONS_FILE) {
+            Err(error) if error.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(error) => {
+                tracing::warn!("can't open {}: {}", SESSIONS_FILE, error);
+                HashMap::new()
+            }
+            Ok(file) => rmp_serde::from_read(file).unwrap_or_else(|error| {
+                tracing::warn!("can't read {}: {}", SESSIONS_FILE, error);
+                HashMap::new()
+            }),
+        };
+        for (id, record) in saved {
+            if is_active(&record) {
+                store.sessions.insert(id, record);
+            }
+        }
+        tokio::spawn(store.clone().write_changes());
+        store
+    }
+
+    /// Writes the sessions a little after they change, batching the changes and
+    /// keeping the blocking writes off the async workers.
+    async fn write_changes(self) {
+        loop {
+            self.changed.notified().await;
+            tokio::time::sleep(WRITE_DELAY).await;
+            let store = self.clone();
+            match tokio::task::spawn_blocking(move || store.write()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => tracing::warn!("can't write {}: {}", SESSIONS_FILE, error),
+                Err(error) => tracing::warn!("can't write {}: {}", SESSIONS_FILE, error),
+            }
+        }
+    }
+
+    /// Writes the sessions to a temporary file then moves it in place, so a crash
+    /// mid-write leaves the previous file intact.
+    fn write(&self) -> std::io::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        self.revoked.retain(|_, expiry| *expiry > now);
+        let sessions: HashMap<Id, Record> = self
+            .sessions
+            .iter()
+            .filter(|entry| is_active(entry.value()))
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        let temp = format!("{}.tmp", SESSIONS_FILE);
+        let mut file = fs::File::create(&temp)?;
+        sessions
+            .serialize(&mut rmp_serde::Serializer::new(&mut file))
+            .map_err(std::io::Error::other)?;
+        file.sync_all()?;
+        fs::rename(&temp, SESSIONS_FILE)
+    }
+
+    /// Removes the session for good, returning whether it existed.
+    fn remove(&self, id: &Id) -> bool {
+        let Some((id, record)) = self.sessions.remove(id) else {
+            return false;
+        };
+        self.revoked.insert(id, record.expiry_date);
+        self.changed.notify_one();
+        true
+    }
+
+    /// Whether the stored expiry of the session is due to be pushed back to `ttl`
+    /// from now.
+    pub fn needs_refresh(&self, id: Id, ttl: Duration) -> bool {
+        self.sessions.get(&id).is_some_and(|record| {
+            record.expiry_date + REFRESH_INTERVAL < OffsetDateTime::now_utc() + ttl
+        })
+    }
+
+    fn user_sessions(&self, user_id: &str) -> Vec<Record> {
+        self.sessions
+            .iter()
+            .filter(|entry| is_active(entry.value()))
+            .filter(|entry| {
+                entry
+                    .value()
+                    .data
+                    .get("user")
+                    .and_then(|user| user.as_str())
+                    == Some(user_id)
+            })
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    /// Active sessions of the user, `current` being the one making the request.
+    pub fn list(&self, user_id: &str, current: Option<Id>) -> Vec<SessionInfo> {
+        self.user_sessions(user_id)
+            .into_iter()
+            .map(|record| SessionInfo {
+                id: record.id.to_string(),
+                created_at: record
+                    .data
+                    .get("created_at")
+                    .and_then(|created_at| created_at.as_i64())
+                    .unwrap_or_default(),
+                expires_at: (record.expiry_date.unix_timestamp_nanos() / 1_000_000) as i64,
+                current: Some(record.id) == current,
+            })
+            .collect()
+    }
+
+    /// Revokes one of the user's sessions, returning whether it existed.
+    pub fn revoke(&self, user_id: &str, session_id: &str) -> bool {
+        self.user_sessions(user_id)
+            .iter()
+            .filter(|record| record.id.to_string() == session_id)
+            .any(|record| self.remove(&record.id))
+    }
+
+    /// Revokes every session of the user but `keep`.
+    pub fn revoke_all(&self, user_id: &str, keep: Option<Id>) {
+        for record in self.user_sessions(user_id) {
+            if Some(record.id) != keep {
+                self.remove(&record.id);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for FileSessionStore {
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        if self.revoked.contains_key(&record.id) {
+            return Ok(());
+        }
+        self.sessions.insert(record.id, record.clone());
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        Ok(self
+            .sessions
+            .get(session_id)
+            .map(|record| record.clone())
+            .filter(is_active))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        self.remove(session_id);
+        Ok(())
+    }
+}
+
+fn is_active(record: &Record) -> bool {
+    record.expiry_date > OffsetDateTime::now_utc()
+}
@@ -0,0 +1,113 @@
+//! Backoff and lockout after failed login attempts.
+
+/// How failures of one key (a username, an address) are throttled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottlePolicy {
+    /// Failures allowed before any delay.
+    pub free_attempts: u32,
+    pub base_delay_ms: i64,
+    pub max_delay_ms: i64,
+    /// Failures after which the key is locked out, if ever.
+    pub lockout_after: Option<u32>,
+    pub lockout_ms: i64,
+    /// Failures older than this are forgotten.
+    pub window_ms: i64,
+}
+
+pub const USERNAME_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 3,
+    base_delay_ms: 1_000,
+    max_delay_ms: 60_000,
+    lockout_after: Some(10),
+    lockout_ms: 15 * 60_000,
+    window_ms: 60 * 60_000,
+};
+
+pub const ADDRESS_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 10,
+    base_delay_ms: 1_000,
+    max_delay_ms: 5 * 60_000,
+    lockout_after: None,
+    lockout_ms: 0,
+    window_ms: 60 * 60_000,
+};
+
+/// Guest accounts created from one address, each counting as a failure.
+pub const GUEST_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 3,
+    base_delay_ms: 60_000,
+    max_delay_ms: 60 * 60_000,
+    lockout_after: None,
+    lockout_ms: 0,
+    window_ms: 24 * 60 * 60_000,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Failures {
+    pub count: u32,
+    pub last_at: i64,
+    pub locked_until: Option<i64>,
+}
+
+impl Failures {
+    pub fn is_stale(&self, policy: &ThrottlePolicy, now: i64) -> bool {
+        now - self.last_at > policy.window_ms && self.locked_until.is_none_or(|until| until <= now)
+    }
+    /// Milliseconds to wait before the next attempt, doubling with every failure past
+    /// the free ones.
+    pub fn retry_after(&self, policy: &ThrottlePolicy, now: i64) -> Option<i64> {
+        if let Some(until) = self.locked_until.filter(|until| *until > now) {
+            return Some(until - now);
+        }
+        if self.is_stale(policy, now) || self.count < policy.free_attempts {
+            return None;
+        }
+        let doublings = (self.count - policy.free_attempts).min(30);
+        let delay = (policy.base_delay_ms << doublings).min(policy.max_delay_ms);
+        Some(self.last_at + delay - now).filter(|wait| *wait > 0)
+    }
+    pub fn fail(&mut self, policy: &ThrottlePolicy, now: i64) {
+        if self.is_stale(policy, now) {
+            *self = Self::default();
+        }
+        self.count += 1;
+        self.last_at = now;
+        if policy
+            .lockout_after
+            .is_some_and(|limit| self.count.is_multiple_of(limit))
+        {
+            self.locked_until = Some(now + policy.lockout_ms);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = USERNAME_POLICY;
+        let mut failures = Failures::default();
+        for _ in 0..3 {
+            failures.fail(&policy, 0);
+        }
+        assert_eq!(failures.retry_after(&policy, 0), Some(1_000));
+        assert_eq!(failures.retry_after(&policy, 1_000), None);
+        failures.fail(&policy, 1_000);
+        assert_eq!(failures.retry_after(&policy, 1_000), Some(2_000));
+        assert_eq!(failures.retry_after(&policy, 2 * 60 * 60_000), None);
+    }
+
+    #[test]
+    fn test_lockout() {
+        let policy = USERNAME_POLICY;
+        let mut failures = Failures::default();
+        for _ in 0..10 {
+            failures.fail(&policy, 0);
+        }
+        assert_eq!(failures.retry_after(&policy, 0), Some(15 * 60_000));
+        assert_eq!(failures.retry_after(&policy, 10 * 60_000), Some(5 * 60_000));
+        assert_eq!(failures.retry_after(&policy, 15 * 60_000), None);
+    }
+}
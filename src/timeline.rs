@@ -0,0 +1,125 @@
+use crate::schemas::{Entity, TimelineEntry};
+
+pub const TIMELINE_LENGTH: usize = 10;
+
+struct Slot {
+    entity_id: uuid::Uuid,
+    scenario_player_index: i64,
+    time: i64,
+    delay: i64,
+}
+
+/// Turns projected when looking for where a unit lands, in case its delay lets the
+/// others act many times before it.
+const LANDING_SEARCH_LIMIT: usize = 1000;
+
+/// Projects the next `length` turns, in the same order as `Game::get_trait_entity`.
+/// Once a unit has acted, it is assumed to take its class's standard attack time.
+pub fn project(entities: &[&Entity], length: usize) -> Vec<TimelineEntry> {
+    Projection::new(entities, None).take(length).collect()
+}
+
+/// Position the unit `caster` would take in the timeline if its next turn was at `time`,
+/// 0 meaning it would play again right away.
+pub fn landing_position(entities: &[&Entity], caster: uuid::Uuid, time: i64) -> Option<usize> {
+    Projection::new(entities, Some((caster, time)))
+        .take(LANDING_SEARCH_LIMIT)
+        .position(|entry| entry.entity_id == caster)
+}
+
+/// Endless sequence of upcoming turns.
+struct Projection {
+    slots: Vec<Slot>,
+}
+
+impl Projection {
+    fn new(entities: &[&Entity], overridden: Option<(uuid::Uuid, i64)>) -> Self {
+        let slots = entities
+            .iter()
+            .map(|e| Slot {
+                entity_id: e.id,
+                scenario_player_index: e.scenario_player_index,
+                time: match overridden {
+                    Some((id, time)) if id == e.id => time,
+                    _ => e.next_move_time,
+                },
+                delay: e.game_class.get_attack_time(),
+            })
+            .collect();
+        Self { slots }
+    }
+}
+
+impl Iterator for Projection {
+    type Item = TimelineEntry;
+
+    fn next(&mut self) -> Option<TimelineEntry> {
+        let slot = self.slots.iter_mut().reduce(|acc, s| {
+            if s.time < acc.time || s.time == acc.time && s.entity_id > acc.entity_id {
+                s
+            } else {
+                acc
+            }
+        })?;
+        let entry = TimelineEntry {
+            entity_id: slot.entity_id,
+            scenario_player_index: slot.scenario_player_index,
+            time: slot.time,
+        };
+        slot.time += slot.delay;
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{charclasses::CharClass, schemas::Coords};
+
+    fn entity(index: i64, next_move_time: i64) -> Entity {
+        Entity {
+            user_id: format!("player{}", index),
+            id: uuid::Uuid::new_v4(),
+            coords: Coords { x: 0, y: 0 },
+            resources: HashMap::new(),
+            scenario_player_index: index,
+            last_move_time: 0,
+            next_move_time,
+            game_class: CharClass::Warrior,
+            log: vec![],
+            plan: None,
+        }
+    }
+
+    #[test]
+    fn test_project() {
+        let first = entity(0, 0);
+        let second = entity(1, 5);
+        let timeline = project(&[&second, &first], 4);
+        let order: Vec<(uuid::Uuid, i64)> =
+            timeline.iter().map(|t| (t.entity_id, t.time)).collect();
+        assert_eq!(
+            order,
+            vec![
+                (first.id, 0),
+                (second.id, 5),
+                (first.id, 20),
+                (second.id, 25)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_landing_position() {
+        let caster = entity(0, 0);
+        let other = entity(1, 10);
+        assert_eq!(landing_position(&[&caster, &other], caster.id, 6), Some(0));
+        assert_eq!(landing_position(&[&caster, &other], caster.id, 20), Some(1));
+        assert_eq!(
+            landing_position(&[&caster, &other], caster.id, 100),
+            Some(5)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Personal API tokens, for bots and scripts to authenticate without a session.
+//!
+//! A token reads `{user_id}.{token_id}.{secret}`; only a hash of the secret is stored.
+//! Secrets are long and random, so a plain SHA-256 is enough and cheap to check on
+//! every request.
+
+use password_hash::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// Tokens a user can hold at once.
+pub const MAX_TOKENS: usize = 20;
+const SECRET_LENGTH: usize = 32;
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TokenScope {
+    /// Only GET requests.
+    Read,
+    /// Everything a player does in games, but not account management.
+    Play,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ApiToken {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub scope: TokenScope,
+    pub hash: String,
+    pub created_at: i64,
+}
+
+/// A token as listed to its owner, without its hash.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: i64,
+}
+
+impl From<&ApiToken> for TokenInfo {
+    fn from(token: &ApiToken) -> Self {
+        Self {
+            id: token.id,
+            name: token.name.clone(),
+            scope: token.scope,
+            created_at: token.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenRequest {
+    pub name: String,
+    pub scope: TokenScope,
+}
+
+/// The newly created token, the only time it is shown in full.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    pub info: TokenInfo,
+    pub token: String,
+}
+
+impl ApiToken {
+    /// The stored token along with the full token to hand out.
+    pub fn generate(user_id: &str, name: String, scope: TokenScope, now: i64) -> (Self, String) {
+        let secret: String = (0..SECRET_LENGTH)
+            .map(|_| ALPHABET[OsRng.next_u32() as usize % ALPHABET.len()] as char)
+            .collect();
+        let token = Self {
+            id: uuid::Uuid::new_v4(),
+            name,
+            scope,
+            hash: digest(secret.as_bytes()),
+            created_at: now,
+        };
+        let full = format!("{}.{}.{}", user_id, token.id, secret);
+        (token, full)
+    }
+    pub fn verify(&self, secret: &str) -> bool {
+        digest(secret.as_bytes())
+            .as_bytes()
+            .ct_eq(self.hash.as_bytes())
+            .into()
+    }
+}
+
+/// Hex SHA-256 of the secret.
+fn digest(secret: &[u8]) -> String {
+    Sha256::digest(secret)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Splits a token into its user id, token id and secret.
+pub fn parse_token(token: &str) -> Option<(&str, uuid::Uuid, &str)> {
+    let mut parts = token.rsplitn(3, '.');
+    let secret = parts.next()?;
+    let id = parts.next()?.parse().ok()?;
+    let user_id = parts.next()?;
+    Some((user_id, id, secret))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_token() {
+        let (token, full) = ApiToken::generate("a.b", "bot".to_string(), TokenScope::Read, 0);
+        let (user_id, id, secret) = parse_token(&full).unwrap();
+        assert_eq!((user_id, id), ("a.b", token.id));
+        assert!(token.verify(secret));
+        assert!(!token.verify("guess"));
+        assert_eq!(parse_token("alice.not-a-uuid.secret"), None);
+        assert_eq!(parse_token("secret"), None);
+    }
+}